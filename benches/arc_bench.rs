@@ -0,0 +1,70 @@
+use atomics::arc::Arc;
+use atomics::biased_arc::BiasedArc;
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::thread;
+
+fn bench_arc_clone(c: &mut Criterion) {
+    c.bench_function("arc_clone_drop", |b| {
+        let a = Arc::new(0u64);
+        b.iter(|| {
+            for _ in 0..1000 {
+                black_box(a.clone());
+            }
+        });
+    });
+}
+
+fn bench_biased_arc_clone(c: &mut Criterion) {
+    c.bench_function("biased_arc_clone_drop", |b| {
+        let a = BiasedArc::new(0u64);
+        b.iter(|| {
+            for _ in 0..1000 {
+                black_box(a.clone());
+            }
+        });
+    });
+}
+
+fn bench_arc_shared(c: &mut Criterion) {
+    c.bench_function("arc_clone_drop_shared", |b| {
+        b.iter(|| {
+            let a = Arc::new(0u64);
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..1000 {
+                            black_box(a.clone());
+                        }
+                    });
+                }
+            });
+        });
+    });
+}
+
+fn bench_biased_arc_shared(c: &mut Criterion) {
+    c.bench_function("biased_arc_clone_drop_shared", |b| {
+        b.iter(|| {
+            let a = BiasedArc::new(0u64);
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..1000 {
+                            black_box(a.clone());
+                        }
+                    });
+                }
+            });
+        });
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_arc_clone,
+    bench_biased_arc_clone,
+    bench_arc_shared,
+    bench_biased_arc_shared
+);
+criterion_main!(benches);
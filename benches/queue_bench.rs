@@ -0,0 +1,90 @@
+use atomics::lfqueue::lockfreequeue::{Hazard, LockFreeQueue, Reclaim};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts allocations, to show what the node pool saves.
+struct Counting;
+
+static NUM_ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        NUM_ALLOCS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn push_pop<R: Reclaim>(q: &LockFreeQueue<u64, R>) {
+    for i in 0..1000 {
+        q.push(i);
+    }
+    for _ in 0..1000 {
+        black_box(q.pop());
+    }
+}
+
+fn bench<R: Reclaim>(c: &mut Criterion, name: &str, q: LockFreeQueue<u64, R>, pooled: bool) {
+    // Warm up the pool, then count what a steady-state round allocates.
+    push_pop(&q);
+    let before = NUM_ALLOCS.load(Ordering::Relaxed);
+    push_pop(&q);
+    let allocs = NUM_ALLOCS.load(Ordering::Relaxed) - before;
+    // Every push allocates a node, unless the pool has one. Reclamation
+    // allocates a little of its own either way.
+    assert_eq!(
+        allocs < 1000,
+        pooled,
+        "{name}: {allocs} allocations per 1000 pushes"
+    );
+
+    c.bench_function(name, |b| b.iter(|| push_pop(&q)));
+}
+
+fn bench_queue(c: &mut Criterion) {
+    bench(c, "queue_push_pop", LockFreeQueue::<u64>::new(), false);
+}
+
+fn bench_queue_pooled(c: &mut Criterion) {
+    bench(
+        c,
+        "queue_push_pop_pooled",
+        LockFreeQueue::<u64>::with_pool(4096),
+        true,
+    );
+}
+
+fn bench_queue_hazard(c: &mut Criterion) {
+    bench(
+        c,
+        "queue_push_pop_hazard",
+        LockFreeQueue::<u64, Hazard>::new(),
+        false,
+    );
+}
+
+fn bench_queue_hazard_pooled(c: &mut Criterion) {
+    bench(
+        c,
+        "queue_push_pop_hazard_pooled",
+        LockFreeQueue::<u64, Hazard>::with_pool(4096),
+        true,
+    );
+}
+
+criterion_group!(
+    benches,
+    bench_queue,
+    bench_queue_pooled,
+    bench_queue_hazard,
+    bench_queue_hazard_pooled
+);
+criterion_main!(benches);
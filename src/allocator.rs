@@ -0,0 +1,166 @@
+use std::{
+    alloc::{self, Layout},
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError;
+
+/// A source of memory for the crate's containers, such as `arc::Arc`.
+///
+/// # Safety
+///
+/// Memory returned by `allocate` must be valid for the requested layout and
+/// stay valid until it's passed to `deallocate`, or until the allocator itself
+/// (and every copy of it) is gone.
+pub unsafe trait Allocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError>;
+
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `allocate` on this allocator
+    /// with the same `layout`.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+/// The global allocator, as used by `Box` and `Vec`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Global;
+
+unsafe impl Allocator for Global {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(unsafe {
+                NonNull::new_unchecked(ptr::without_provenance_mut(layout.align()))
+            });
+        }
+        NonNull::new(unsafe { alloc::alloc(layout) }).ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            unsafe { alloc::dealloc(ptr.as_ptr(), layout) }
+        }
+    }
+}
+
+unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { (**self).deallocate(ptr, layout) }
+    }
+}
+
+/// A fixed-size arena that hands out memory by bumping an offset.
+///
+/// Allocation is a single CAS, so it can be shared between threads through
+/// `&Bump`. `deallocate` does nothing: the memory is only given back when the
+/// arena is reset or dropped, which the borrow checker keeps from happening
+/// while anything allocated from it is still alive.
+pub struct Bump {
+    chunk: NonNull<u8>,
+    capacity: usize,
+    offset: AtomicUsize,
+}
+
+unsafe impl Send for Bump {}
+unsafe impl Sync for Bump {}
+
+impl Bump {
+    const CHUNK_ALIGN: usize = 16;
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        let layout = Self::chunk_layout(capacity);
+        let chunk = match NonNull::new(unsafe { alloc::alloc(layout) }) {
+            Some(chunk) => chunk,
+            None => alloc::handle_alloc_error(layout),
+        };
+        Self {
+            chunk,
+            capacity,
+            offset: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of bytes handed out so far, including alignment padding.
+    pub fn allocated(&self) -> usize {
+        self.offset.load(Ordering::Relaxed)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Makes the whole arena available again.
+    pub fn reset(&mut self) {
+        *self.offset.get_mut() = 0;
+    }
+
+    fn chunk_layout(capacity: usize) -> Layout {
+        Layout::from_size_align(capacity, Self::CHUNK_ALIGN).unwrap()
+    }
+}
+
+unsafe impl Allocator for Bump {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        let base = self.chunk.as_ptr().addr();
+        let mut offset = self.offset.load(Ordering::Relaxed);
+        loop {
+            let start = (base + offset).next_multiple_of(layout.align()) - base;
+            let end = start
+                .checked_add(layout.size())
+                .filter(|&end| end <= self.capacity)
+                .ok_or(AllocError)?;
+            // Relaxed is enough: the offset doesn't publish any data.
+            match self.offset.compare_exchange_weak(
+                offset,
+                end,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(unsafe { self.chunk.add(start) }),
+                Err(e) => offset = e,
+            }
+        }
+    }
+
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {}
+}
+
+impl Drop for Bump {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.chunk.as_ptr(), Self::chunk_layout(self.capacity)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_bump() {
+        let bump = Bump::with_capacity(4096);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..16 {
+                        let layout = Layout::new::<u64>();
+                        let ptr = bump.allocate(layout).unwrap();
+                        assert_eq!(ptr.as_ptr().addr() % layout.align(), 0);
+                        unsafe { ptr.cast::<u64>().write(7) };
+                    }
+                });
+            }
+        });
+        assert_eq!(bump.allocated(), 4 * 16 * 8);
+
+        assert_eq!(bump.allocate(Layout::new::<[u8; 4096]>()), Err(AllocError));
+    }
+}
@@ -1,71 +1,595 @@
 use std::{
-    ops::Deref,
-    ptr::NonNull,
+    alloc::{Layout, handle_alloc_error},
+    cell::UnsafeCell,
+    mem::{self, ManuallyDrop, MaybeUninit},
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
     sync::atomic::{AtomicUsize, Ordering, fence},
 };
 
-struct ArcData<T> {
+use crate::allocator::{AllocError, Allocator, Global};
+
+// `repr(C)` so that the offset of `data` only depends on its alignment,
+// which lets us allocate `ArcData<[T]>` and friends by hand.
+#[repr(C)]
+struct ArcData<T: ?Sized, A> {
+    /// Number of `Arc`s.
     ref_count: AtomicUsize,
-    data: T,
+    /// Number of `Weak`s, plus one if there are any `Arc`s.
+    weak_count: AtomicUsize,
+    /// Where the allocation came from, and goes back to with the last `Weak`.
+    alloc: A,
+    /// The data. Dropped if there are only weak pointers left.
+    data: UnsafeCell<ManuallyDrop<T>>,
 }
 
-pub struct Arc<T> {
-    ptr: NonNull<ArcData<T>>,
+pub struct Arc<T: ?Sized, A: Allocator = Global> {
+    ptr: NonNull<ArcData<T, A>>,
 }
 
-unsafe impl<T: Send + Sync> Send for Arc<T> {}
-unsafe impl<T: Send + Sync> Sync for Arc<T> {}
+unsafe impl<T: ?Sized + Send + Sync, A: Allocator + Send + Sync> Send for Arc<T, A> {}
+unsafe impl<T: ?Sized + Send + Sync, A: Allocator + Send + Sync> Sync for Arc<T, A> {}
+
+pub struct Weak<T: ?Sized, A: Allocator = Global> {
+    ptr: NonNull<ArcData<T, A>>,
+}
+
+unsafe impl<T: ?Sized + Send + Sync, A: Allocator + Send + Sync> Send for Weak<T, A> {}
+unsafe impl<T: ?Sized + Send + Sync, A: Allocator + Send + Sync> Sync for Weak<T, A> {}
+
+/// An `Arc` that is known to be the only reference to its data, so it can
+/// hand out `&mut T` and be set up without any atomic operations. Turn it
+/// into an ordinary `Arc` with `shareable` once initialization is done.
+pub struct UniqueArc<T: ?Sized, A: Allocator = Global> {
+    ptr: NonNull<ArcData<T, A>>,
+}
+
+// Like `Box`, since nothing else can reach the data.
+unsafe impl<T: ?Sized + Send, A: Allocator + Send> Send for UniqueArc<T, A> {}
+unsafe impl<T: ?Sized + Sync, A: Allocator + Sync> Sync for UniqueArc<T, A> {}
 
 impl<T> Arc<T> {
     pub fn new(data: T) -> Self {
-        let arc = Box::leak(Box::new(ArcData {
-            ref_count: AtomicUsize::new(1),
-            data,
-        }));
-        Arc {
-            ptr: NonNull::from(arc),
+        Self::new_in(data, Global)
+    }
+
+    /// Creates an `Arc` whose data can hold a `Weak` pointer to itself.
+    ///
+    /// `data_fn` gets a `Weak` to the allocation that is being set up. It can
+    /// be cloned and stored, but upgrading it fails until `new_cyclic` returns.
+    pub fn new_cyclic(data_fn: impl FnOnce(&Weak<T>) -> T) -> Self {
+        let inner =
+            unsafe { Self::allocate_for_layout(Layout::new::<T>(), Global, |mem| mem.cast()) };
+        // Start without any Arcs, and with one Weak that becomes the implicit
+        // weak pointer of all Arcs once the data is in place.
+        unsafe { (*inner).ref_count.store(0, Ordering::Relaxed) };
+        let weak = Weak {
+            ptr: unsafe { NonNull::new_unchecked(inner) },
+        };
+
+        // If this panics, dropping `weak` frees the allocation.
+        let data = data_fn(&weak);
+
+        unsafe {
+            ptr::addr_of_mut!((*inner).data).cast::<T>().write(data);
+        }
+        // Release matches the Acquire in `Weak::upgrade`, so that Weaks
+        // handed out by `data_fn` see the data once they can upgrade.
+        weak.data().ref_count.store(1, Ordering::Release);
+        let ptr = weak.ptr;
+        mem::forget(weak);
+        Arc { ptr }
+    }
+}
+
+impl<T, A: Allocator> Arc<T, A> {
+    /// Like `new`, but the allocation comes from (and returns to) `alloc`.
+    pub fn new_in(data: T, alloc: A) -> Self {
+        match Self::try_new_in(data, alloc) {
+            Ok(arc) => arc,
+            Err(_) => handle_alloc_error(Layout::new::<ArcData<T, A>>()),
         }
     }
 
+    /// Like `new_in`, but reports running out of memory instead of aborting,
+    /// which is useful for fixed-size allocators such as `Bump`.
+    pub fn try_new_in(data: T, alloc: A) -> Result<Self, AllocError> {
+        let ptr = alloc
+            .allocate(Layout::new::<ArcData<T, A>>())?
+            .cast::<ArcData<T, A>>();
+        unsafe {
+            ptr.write(ArcData {
+                ref_count: AtomicUsize::new(1),
+                weak_count: AtomicUsize::new(1),
+                alloc,
+                data: UnsafeCell::new(ManuallyDrop::new(data)),
+            });
+        }
+        Ok(Arc { ptr })
+    }
+
+    /// Returns a mutable reference to the data, cloning it into a new
+    /// allocation first if other `Arc`s or `Weak`s point to it.
+    pub fn make_mut(arc: &mut Self) -> &mut T
+    where
+        T: Clone,
+        A: Clone,
+    {
+        if Arc::get_mut(arc).is_none() {
+            *arc = Arc::new_in(T::clone(arc), A::clone(Arc::allocator(arc)));
+        }
+        // Safety: `arc` was either unique according to `get_mut`, or is a
+        // freshly allocated Arc nobody else has seen. Holding `&mut arc`
+        // prevents new clones or downgrades in the meantime.
+        unsafe { &mut *arc.data().data.get() }
+    }
+
+    /// Returns the inner value if `arc` is the only strong reference,
+    /// otherwise hands `arc` back unchanged.
+    pub fn try_unwrap(arc: Self) -> Result<T, Self> {
+        // Acquire to match Arc::drop's Release decrement.
+        if arc
+            .data()
+            .ref_count
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(arc);
+        }
+        let arc = ManuallyDrop::new(arc);
+        // Safety: The strong count is zero, so nothing else will touch the data.
+        let data = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+        drop(Weak { ptr: arc.ptr });
+        Ok(data)
+    }
+
+    /// Drops `arc`, returning the inner value if it was the last strong reference.
+    ///
+    /// Unlike `try_unwrap`, when several threads call this concurrently on the
+    /// last clones, exactly one of them gets the value.
+    pub fn into_inner(arc: Self) -> Option<T> {
+        let arc = ManuallyDrop::new(arc);
+        if arc.data().ref_count.fetch_sub(1, Ordering::Release) != 1 {
+            return None;
+        }
+        fence(Ordering::Acquire);
+        // Safety: The strong count is zero, so nothing else will touch the data.
+        let data = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+        drop(Weak { ptr: arc.ptr });
+        Some(data)
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Arc<T, A> {
     pub fn get_mut(arc: &mut Self) -> Option<&mut T> {
-        if arc.data().ref_count.load(Ordering::Relaxed) == 1 {
-            fence(Ordering::Acquire);
-            unsafe { return Some(&mut arc.ptr.as_mut().data) }
+        // Acquire matches Weak::drop's Release decrement, to make sure any
+        // upgraded pointers are visible in the next ref_count.load.
+        //
+        // While `weak_count` is locked to usize::MAX, `downgrade` spins,
+        // so no new Weak can appear between the two checks.
+        if arc
+            .data()
+            .weak_count
+            .compare_exchange(1, usize::MAX, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+        let is_unique = arc.data().ref_count.load(Ordering::Relaxed) == 1;
+        // Release matches the Acquire increment in `downgrade`, to make sure any
+        // changes to ref_count that come after `downgrade` don't change the
+        // is_unique result above.
+        arc.data().weak_count.store(1, Ordering::Release);
+        if !is_unique {
+            return None;
+        }
+        // Acquire to match Arc::drop's Release decrement, to make sure nothing
+        // else is accessing the data.
+        fence(Ordering::Acquire);
+        unsafe { Some(&mut *arc.data().data.get()) }
+    }
+
+    /// Consumes `arc` and returns a pointer to the data, without touching the
+    /// reference count. Use `from_raw` to get the `Arc` back.
+    pub fn into_raw(arc: Self) -> *const T {
+        let arc = ManuallyDrop::new(arc);
+        // `UnsafeCell` and `ManuallyDrop` are both `repr(transparent)`.
+        arc.data().data.get() as *const T
+    }
+
+    /// Rebuilds an `Arc` from a pointer returned by `into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Arc::<T, A>::into_raw`, and each pointer may only
+    /// be turned back into an `Arc` once.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // Safety: `ptr` points to live data, so its layout can be read.
+        let (_, offset) = arc_data_layout::<A>(Layout::for_value(unsafe { &*ptr }));
+        let ptr = unsafe { ptr.byte_sub(offset) } as *mut ArcData<T, A>;
+        Arc {
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+        }
+    }
+
+    /// Returns true if both `Arc`s point to the same allocation.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        ptr::addr_eq(a.ptr.as_ptr(), b.ptr.as_ptr())
+    }
+
+    pub fn allocator(arc: &Self) -> &A {
+        &arc.data().alloc
+    }
+
+    pub fn downgrade(arc: &Self) -> Weak<T, A> {
+        let mut n = arc.data().weak_count.load(Ordering::Relaxed);
+        loop {
+            // `get_mut` is currently checking for uniqueness.
+            if n == usize::MAX {
+                std::hint::spin_loop();
+                n = arc.data().weak_count.load(Ordering::Relaxed);
+                continue;
+            }
+            assert!(n < usize::MAX - 1);
+            // Acquire synchronises with get_mut's release-store.
+            match arc.data().weak_count.compare_exchange_weak(
+                n,
+                n + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Weak { ptr: arc.ptr },
+                Err(e) => n = e,
+            }
+        }
+    }
+
+    pub fn strong_count(arc: &Self) -> usize {
+        arc.data().ref_count.load(Ordering::Relaxed)
+    }
+
+    pub fn weak_count(arc: &Self) -> usize {
+        let n = arc.data().weak_count.load(Ordering::Relaxed);
+        // If `get_mut` holds the lock, there were no Weaks when it took it.
+        if n == usize::MAX { 0 } else { n - 1 }
+    }
+
+    fn data(&self) -> &ArcData<T, A> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Allocates an `ArcData<T, A>` with both counts set to one, leaving the
+    /// data uninitialized. `mem_to_arc_data` turns the raw allocation into a
+    /// (possibly fat) pointer with the right metadata.
+    unsafe fn allocate_for_layout(
+        value_layout: Layout,
+        alloc: A,
+        mem_to_arc_data: impl FnOnce(*mut u8) -> *mut ArcData<T, A>,
+    ) -> *mut ArcData<T, A> {
+        let (layout, _) = arc_data_layout::<A>(value_layout);
+        let mem = match alloc.allocate(layout) {
+            Ok(mem) => mem,
+            Err(_) => handle_alloc_error(layout),
+        };
+        let inner = mem_to_arc_data(mem.as_ptr());
+        unsafe {
+            ptr::addr_of_mut!((*inner).ref_count).write(AtomicUsize::new(1));
+            ptr::addr_of_mut!((*inner).weak_count).write(AtomicUsize::new(1));
+            ptr::addr_of_mut!((*inner).alloc).write(alloc);
+        }
+        inner
+    }
+}
+
+impl<T: ?Sized> Arc<T> {
+    /// Allocates an `Arc` for a value with `value_layout` and lets `init`
+    /// write the value in place. `metadata` is any pointer carrying the
+    /// slice length or vtable of the value; its address is ignored.
+    ///
+    /// # Safety
+    ///
+    /// `value_layout` must be the layout of the value described by `metadata`,
+    /// and `init` must fully initialize the value it's given. If it panics
+    /// instead, it must drop whatever it initialized; the allocation is
+    /// freed here.
+    pub(crate) unsafe fn new_in_place(
+        value_layout: Layout,
+        metadata: *const T,
+        init: impl FnOnce(*mut T),
+    ) -> Self {
+        struct Deallocate(NonNull<u8>, Layout);
+
+        impl Drop for Deallocate {
+            fn drop(&mut self) {
+                unsafe { Global.deallocate(self.0, self.1) }
+            }
+        }
+
+        unsafe {
+            let inner = Self::allocate_for_layout(value_layout, Global, |mem| {
+                set_data_ptr(metadata.cast_mut() as *mut ArcData<T, Global>, mem)
+            });
+            let (layout, _) = arc_data_layout::<Global>(value_layout);
+            let guard = Deallocate(NonNull::new_unchecked(inner.cast()), layout);
+            init(ptr::addr_of_mut!((*inner).data) as *mut T);
+            mem::forget(guard);
+            Arc {
+                ptr: NonNull::new_unchecked(inner),
+            }
         }
-        None
+    }
+}
+
+impl<T> Arc<[T]> {
+    /// Moves `len` values starting at `src` into a new `Arc<[T]>`.
+    /// The caller must make sure the originals are not dropped.
+    unsafe fn copy_from_slice(src: *const T, len: usize) -> Self {
+        let value_layout = Layout::array::<T>(len).unwrap();
+        let inner = unsafe {
+            Self::allocate_for_layout(value_layout, Global, |mem| {
+                ptr::slice_from_raw_parts_mut(mem.cast::<T>(), len) as *mut ArcData<[T], Global>
+            })
+        };
+        unsafe {
+            let dst = ptr::addr_of_mut!((*inner).data).cast::<T>();
+            ptr::copy_nonoverlapping(src, dst, len);
+            Arc {
+                ptr: NonNull::new_unchecked(inner),
+            }
+        }
+    }
+}
+
+/// Returns the layout of an `ArcData` holding a value with `value_layout`,
+/// and the offset of the value within it.
+fn arc_data_layout<A>(value_layout: Layout) -> (Layout, usize) {
+    // The fields before `data`, without the padding `ArcData<(), A>` would
+    // have at the end, as a small value may go in there.
+    let (header, _) = Layout::new::<[AtomicUsize; 2]>()
+        .extend(Layout::new::<A>())
+        .unwrap();
+    let (layout, offset) = header.extend(value_layout).unwrap();
+    (layout.pad_to_align(), offset)
+}
+
+/// Drops the allocator stored in `ptr` after giving the allocation back to it.
+///
+/// # Safety
+///
+/// Nothing may use `ptr` afterwards, and the data must already have been
+/// dropped or moved out.
+unsafe fn deallocate<T: ?Sized, A: Allocator>(ptr: NonNull<ArcData<T, A>>) {
+    unsafe {
+        let layout = Layout::for_value(ptr.as_ref());
+        let alloc = ptr::read(ptr::addr_of!((*ptr.as_ptr()).alloc));
+        alloc.deallocate(ptr.cast(), layout);
+    }
+}
+
+/// Replaces the address of a (possibly fat) pointer, keeping its metadata.
+unsafe fn set_data_ptr<T: ?Sized, U>(mut ptr: *mut T, data: *mut U) -> *mut T {
+    unsafe { ptr::write(ptr::addr_of_mut!(ptr).cast::<*mut u8>(), data.cast::<u8>()) };
+    ptr
+}
+
+impl<T> UniqueArc<T> {
+    pub fn new(data: T) -> Self {
+        Self::new_in(data, Global)
     }
 
-    fn data(&self) -> &ArcData<T> {
+    pub fn new_uninit() -> UniqueArc<MaybeUninit<T>> {
+        UniqueArc::new(MaybeUninit::uninit())
+    }
+}
+
+impl<T, A: Allocator> UniqueArc<T, A> {
+    pub fn new_in(data: T, alloc: A) -> Self {
+        let arc = ManuallyDrop::new(Arc::new_in(data, alloc));
+        UniqueArc { ptr: arc.ptr }
+    }
+}
+
+impl<T, A: Allocator> UniqueArc<MaybeUninit<T>, A> {
+    pub fn write(mut unique: Self, value: T) -> UniqueArc<T, A> {
+        MaybeUninit::write(&mut *unique, value);
+        unsafe { Self::assume_init(unique) }
+    }
+
+    /// # Safety
+    ///
+    /// The data must have been initialized.
+    pub unsafe fn assume_init(unique: Self) -> UniqueArc<T, A> {
+        let unique = ManuallyDrop::new(unique);
+        // `MaybeUninit<T>` has the same layout as `T`.
+        UniqueArc {
+            ptr: unique.ptr.cast(),
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> UniqueArc<T, A> {
+    /// Turns this into an `Arc` that can be cloned and shared.
+    pub fn shareable(unique: Self) -> Arc<T, A> {
+        let unique = ManuallyDrop::new(unique);
+        // The counts were set to one when the allocation was made,
+        // which is exactly what a single Arc needs.
+        Arc { ptr: unique.ptr }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Weak<T, A> {
+    pub fn upgrade(&self) -> Option<Arc<T, A>> {
+        let mut n = self.data().ref_count.load(Ordering::Relaxed);
+        loop {
+            if n == 0 {
+                return None;
+            }
+            assert!(n < usize::MAX);
+            // Acquire synchronises with the Release store in `new_cyclic`,
+            // which may initialize the data after this Weak was created.
+            match self.data().ref_count.compare_exchange_weak(
+                n,
+                n + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(Arc { ptr: self.ptr }),
+                Err(e) => n = e,
+            }
+        }
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.data().ref_count.load(Ordering::Relaxed)
+    }
+
+    pub fn weak_count(&self) -> usize {
+        let weak = self.data().weak_count.load(Ordering::Relaxed);
+        // Subtract the implicit weak pointer held by the Arcs, if any.
+        if self.strong_count() > 0 {
+            weak - 1
+        } else {
+            weak
+        }
+    }
+
+    fn data(&self) -> &ArcData<T, A> {
         unsafe { self.ptr.as_ref() }
     }
 }
 
-impl<T> Deref for Arc<T> {
+impl<T: ?Sized, A: Allocator> Deref for Arc<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: Since there's an Arc to the data,
+        // the data exists and may be shared.
+        unsafe { &*self.data().data.get() }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Deref for UniqueArc<T, A> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        &self.data().data
+        unsafe { &*self.ptr.as_ref().data.get() }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> DerefMut for UniqueArc<T, A> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: Nothing else points to the data.
+        unsafe { &mut *self.ptr.as_ref().data.get() }
     }
 }
 
-impl<T> Clone for Arc<T> {
+impl<T: ?Sized, A: Allocator> Clone for Arc<T, A> {
     fn clone(&self) -> Self {
         self.data().ref_count.fetch_add(1, Ordering::Relaxed);
         Self { ptr: self.ptr }
     }
 }
 
-impl<T> Drop for Arc<T> {
+impl<T: ?Sized, A: Allocator> Clone for Weak<T, A> {
+    fn clone(&self) -> Self {
+        self.data().weak_count.fetch_add(1, Ordering::Relaxed);
+        Self { ptr: self.ptr }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Drop for Arc<T, A> {
     fn drop(&mut self) {
         if self.data().ref_count.fetch_sub(1, Ordering::Release) == 1 {
             fence(Ordering::Acquire);
+            // Safety: The strong count is zero,
+            // so nothing will access the data anymore.
             unsafe {
-                drop(Box::from_raw(self.ptr.as_ptr()));
+                ManuallyDrop::drop(&mut *self.data().data.get());
             }
+            // Now that there's no `Arc<T>`s left,
+            // drop the implicit weak pointer that represented all `Arc<T>`s.
+            drop(Weak { ptr: self.ptr });
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Drop for UniqueArc<T, A> {
+    fn drop(&mut self) {
+        // No other pointers exist, so there's no need to touch the counts.
+        unsafe {
+            ManuallyDrop::drop(&mut *self.ptr.as_ref().data.get());
+            deallocate(self.ptr);
         }
     }
 }
 
+impl<T: ?Sized, A: Allocator> Drop for Weak<T, A> {
+    fn drop(&mut self) {
+        if self.data().weak_count.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            unsafe {
+                deallocate(self.ptr);
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for Arc<T> {
+    fn from(value: Box<T>) -> Self {
+        let value_layout = Layout::for_value(&*value);
+        let src = Box::into_raw(value);
+        unsafe {
+            let inner = Self::allocate_for_layout(value_layout, Global, |mem| {
+                set_data_ptr(src as *mut ArcData<T, Global>, mem)
+            });
+            let dst = ptr::addr_of_mut!((*inner).data).cast::<u8>();
+            ptr::copy_nonoverlapping(src.cast::<u8>(), dst, value_layout.size());
+            // Free the box's allocation without dropping the moved value.
+            drop(Box::from_raw(src as *mut ManuallyDrop<T>));
+            Arc {
+                ptr: NonNull::new_unchecked(inner),
+            }
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Arc<[T]> {
+    fn from(mut value: Vec<T>) -> Self {
+        unsafe {
+            let arc = Arc::copy_from_slice(value.as_ptr(), value.len());
+            // The elements now belong to the Arc, only free the buffer.
+            value.set_len(0);
+            arc
+        }
+    }
+}
+
+impl<T: Clone> From<&[T]> for Arc<[T]> {
+    fn from(value: &[T]) -> Self {
+        Arc::from(value.to_vec())
+    }
+}
+
+impl From<&str> for Arc<str> {
+    fn from(value: &str) -> Self {
+        let bytes = Arc::<[u8]>::from(value.as_bytes());
+        // Safety: `str` has the same layout as `[u8]` and the bytes are valid UTF-8.
+        unsafe { Arc::from_raw(Arc::into_raw(bytes) as *const str) }
+    }
+}
+
+impl From<String> for Arc<str> {
+    fn from(value: String) -> Self {
+        Arc::from(value.as_str())
+    }
+}
+
+impl<T> FromIterator<T> for Arc<[T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Arc::from(iter.into_iter().collect::<Vec<T>>())
+    }
+}
+
 mod tests {
 
     #[test]
@@ -97,4 +621,259 @@ mod tests {
 
         assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
     }
+
+    #[test]
+    fn test_weak() {
+        use super::*;
+        use std::thread;
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct DectorDrop;
+
+        impl Drop for DectorDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut a = Arc::new(("a", DectorDrop));
+        let b = Arc::downgrade(&a);
+        let c = Arc::downgrade(&a);
+
+        assert_eq!(Arc::strong_count(&a), 1);
+        assert_eq!(Arc::weak_count(&a), 2);
+        // Outstanding Weaks could upgrade, so `a` is not unique.
+        assert!(Arc::get_mut(&mut a).is_none());
+
+        let t = thread::spawn(move || {
+            let b = b.upgrade().unwrap();
+            assert_eq!(b.0, "a");
+        });
+        assert_eq!(a.0, "a");
+        t.join().unwrap();
+
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        assert!(c.upgrade().is_some());
+
+        drop(a);
+
+        // The data is dropped, but the allocation lives on for `c`.
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        assert!(c.upgrade().is_none());
+        assert_eq!(c.strong_count(), 0);
+        assert_eq!(c.weak_count(), 1);
+    }
+
+    #[test]
+    fn test_ownership() {
+        use super::*;
+        use std::sync::atomic::AtomicPtr;
+
+        let mut a = Arc::new(vec![1, 2]);
+        // Unique: mutated in place.
+        Arc::make_mut(&mut a).push(3);
+
+        let b = a.clone();
+        // Shared: `a` gets its own copy, `b` is untouched.
+        Arc::make_mut(&mut a).push(4);
+        assert_eq!(*a, [1, 2, 3, 4]);
+        assert_eq!(*b, [1, 2, 3]);
+        assert!(!Arc::ptr_eq(&a, &b));
+
+        let c = b.clone();
+        let b = Arc::try_unwrap(b).unwrap_err();
+        assert_eq!(Arc::strong_count(&b), 2);
+        assert_eq!(Arc::into_inner(c), None);
+        assert_eq!(Arc::try_unwrap(b).ok(), Some(vec![1, 2, 3]));
+
+        let w = Arc::downgrade(&a);
+        assert_eq!(Arc::into_inner(a), Some(vec![1, 2, 3, 4]));
+        assert!(w.upgrade().is_none());
+
+        let a = Arc::new(String::from("raw"));
+        let slot = AtomicPtr::new(Arc::into_raw(a.clone()).cast_mut());
+        let b = unsafe { Arc::from_raw(slot.load(Ordering::Acquire)) };
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(*b, "raw");
+        drop(b);
+        assert_eq!(Arc::strong_count(&a), 1);
+    }
+
+    #[test]
+    fn test_unsized() {
+        use super::*;
+        use std::thread;
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct DectorDrop;
+
+        impl Drop for DectorDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let a: Arc<[DectorDrop]> = (0..3).map(|_| DectorDrop).collect();
+        let b = a.clone();
+        thread::spawn(move || assert_eq!(b.len(), 3))
+            .join()
+            .unwrap();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(a);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+
+        let s = Arc::<str>::from("hello");
+        let w = Arc::downgrade(&s);
+        let raw = Arc::into_raw(s);
+        let s: Arc<str> = unsafe { Arc::from_raw(raw) };
+        assert_eq!(&*w.upgrade().unwrap(), "hello");
+        assert_eq!(Arc::<[u64]>::from(vec![1, 2, 3])[..], [1, 2, 3]);
+
+        trait Plugin: Send + Sync {
+            fn name(&self) -> &str;
+        }
+        impl Plugin for (String, DectorDrop) {
+            fn name(&self) -> &str {
+                &self.0
+            }
+        }
+
+        let p: Arc<dyn Plugin> =
+            Arc::from(Box::new((s.to_string(), DectorDrop)) as Box<dyn Plugin>);
+        let q = p.clone();
+        assert_eq!(thread::spawn(move || q.name().len()).join().unwrap(), 5);
+        assert_eq!(p.name(), "hello");
+        drop(p);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_cyclic() {
+        use super::*;
+        use std::thread;
+
+        struct Node {
+            me: Weak<Node>,
+            name: &'static str,
+        }
+
+        let node = Arc::new_cyclic(|me| {
+            // Not upgradable until the data is in place.
+            assert!(me.upgrade().is_none());
+            Node {
+                me: me.clone(),
+                name: "root",
+            }
+        });
+        assert_eq!(Arc::strong_count(&node), 1);
+        assert_eq!(Arc::weak_count(&node), 1);
+
+        let me = node.me.clone();
+        let t = thread::spawn(move || me.upgrade().unwrap().name);
+        assert_eq!(t.join().unwrap(), "root");
+
+        let this = node.me.upgrade().unwrap();
+        assert!(Arc::ptr_eq(&this, &node));
+        drop(this);
+        let me = node.me.clone();
+        drop(node);
+        assert!(me.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_unique() {
+        use super::*;
+        use std::thread;
+
+        let mut unique = UniqueArc::new(Vec::new());
+        unique.push(1);
+        unique.push(2);
+        let a = UniqueArc::shareable(unique);
+        let b = a.clone();
+        thread::spawn(move || assert_eq!(*b, [1, 2]))
+            .join()
+            .unwrap();
+        assert_eq!(Arc::strong_count(&a), 1);
+
+        let mut slot = UniqueArc::<String>::new_uninit();
+        slot.write(String::from("later"));
+        let s = UniqueArc::shareable(unsafe { UniqueArc::assume_init(slot) });
+        assert_eq!(*s, "later");
+        assert_eq!(Arc::try_unwrap(s).ok().unwrap(), "later");
+
+        let dropped = UniqueArc::write(UniqueArc::new_uninit(), String::from("dropped"));
+        drop(dropped);
+    }
+
+    #[test]
+    fn test_alloc() {
+        use super::*;
+        use crate::allocator::Bump;
+        use std::thread;
+
+        struct Counting<'a>(&'a AtomicUsize);
+
+        unsafe impl Allocator for Counting<'_> {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.0.fetch_sub(1, Ordering::Relaxed);
+                unsafe { Global.deallocate(ptr, layout) }
+            }
+        }
+
+        let live = AtomicUsize::new(0);
+        let a = Arc::new_in(String::from("counted"), Counting(&live));
+        let w = Arc::downgrade(&a);
+        assert_eq!(live.load(Ordering::Relaxed), 1);
+        drop(a);
+        // The allocation is only given back with the last Weak.
+        assert_eq!(live.load(Ordering::Relaxed), 1);
+        drop(w);
+        assert_eq!(live.load(Ordering::Relaxed), 0);
+
+        let bump = Bump::with_capacity(1024);
+        let nodes: Vec<_> = (0..8).map(|i| Arc::new_in(i, &bump)).collect();
+        let used = bump.allocated();
+        thread::scope(|s| {
+            for node in &nodes {
+                let node = node.clone();
+                s.spawn(move || assert!(*node < 8));
+            }
+        });
+        assert_eq!(bump.allocated(), used);
+
+        let mut shared = nodes[0].clone();
+        *Arc::make_mut(&mut shared) += 100;
+        assert_eq!((*nodes[0], *shared), (0, 100));
+        assert!(bump.allocated() > used);
+
+        let small = Bump::with_capacity(16);
+        assert!(Arc::try_new_in([0u8; 64], &small).is_err());
+
+        // An allocator whose size isn't a multiple of the counts' alignment,
+        // so the data goes in what would be padding after the header.
+        struct Tagged(u32);
+
+        unsafe impl Allocator for Tagged {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                unsafe { Global.deallocate(ptr, layout) }
+            }
+        }
+
+        let a = Arc::new_in(7u8, Tagged(1));
+        let b = unsafe { Arc::<u8, Tagged>::from_raw(Arc::into_raw(a.clone())) };
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!((*b, Arc::strong_count(&b)), (7, 2));
+        let c = Arc::new_cyclic(|_| 9u8);
+        let c = unsafe { Arc::<u8>::from_raw(Arc::into_raw(c)) };
+        assert_eq!((*c, Arc::strong_count(&c)), (9, 1));
+    }
 }
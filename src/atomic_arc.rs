@@ -0,0 +1,168 @@
+use std::{
+    hint::spin_loop,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crate::{arc::Arc, mutex::Mutex};
+
+/// A slot holding an `Arc<T>` that readers can load without taking a lock.
+///
+/// A reader registers in one of two counters, picked by the current `epoch`,
+/// before it loads the pointer and bumps the strong count. A writer swaps the
+/// pointer in, moves `epoch` on, and then waits for the counter of the old
+/// epoch to drain before it drops the old `Arc`. Readers arriving after the
+/// switch register in the other counter, so a stream of readers can't starve
+/// the writer.
+pub struct AtomicArc<T> {
+    ptr: AtomicPtr<T>,
+    epoch: AtomicUsize,
+    readers: [AtomicUsize; 2],
+    // Writers are expected to be rare, so they simply take turns.
+    writer: Mutex<()>,
+    _marker: PhantomData<Arc<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for AtomicArc<T> {}
+unsafe impl<T: Send + Sync> Sync for AtomicArc<T> {}
+
+impl<T> AtomicArc<T> {
+    pub fn new(arc: Arc<T>) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Arc::into_raw(arc).cast_mut()),
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            writer: Mutex::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn load(&self) -> Arc<T> {
+        let index = self.enter();
+        let ptr = self.ptr.load(Ordering::SeqCst);
+        // Safety: A writer that swapped `ptr` out waits for us to leave
+        // before dropping it, so the strong count is still at least one.
+        let arc = ManuallyDrop::new(unsafe { Arc::from_raw(ptr) });
+        let result = Arc::clone(&arc);
+        // Release so the writer waiting on this counter sees our clone.
+        self.readers[index].fetch_sub(1, Ordering::Release);
+        result
+    }
+
+    pub fn store(&self, new: Arc<T>) {
+        drop(self.swap(new));
+    }
+
+    pub fn swap(&self, new: Arc<T>) -> Arc<T> {
+        let _guard = self.writer.lock();
+        self.replace(new)
+    }
+
+    /// Stores `new` if the slot still points to the same allocation as
+    /// `current`. Returns the previous value on success, and hands `new`
+    /// back otherwise.
+    pub fn compare_and_swap(&self, current: &Arc<T>, new: Arc<T>) -> Result<Arc<T>, Arc<T>> {
+        let _guard = self.writer.lock();
+        // Only writers modify `ptr`, and we hold the writer lock.
+        if !ptr::eq(self.ptr.load(Ordering::Relaxed), &**current) {
+            return Err(new);
+        }
+        Ok(self.replace(new))
+    }
+
+    /// Registers the current thread as a reader and returns the index of the
+    /// counter it used.
+    fn enter(&self) -> usize {
+        loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            let index = epoch & 1;
+            self.readers[index].fetch_add(1, Ordering::SeqCst);
+            // If a writer moved the epoch on in the meantime, it may already
+            // have checked this counter, so it wouldn't wait for us.
+            if self.epoch.load(Ordering::SeqCst) == epoch {
+                return index;
+            }
+            self.readers[index].fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Must be called with the writer lock held.
+    fn replace(&self, new: Arc<T>) -> Arc<T> {
+        let old = self
+            .ptr
+            .swap(Arc::into_raw(new).cast_mut(), Ordering::SeqCst);
+
+        // Any reader that may have seen `old` registered under this epoch.
+        let index = self.epoch.fetch_add(1, Ordering::SeqCst) & 1;
+        while self.readers[index].load(Ordering::SeqCst) != 0 {
+            spin_loop();
+        }
+
+        // Safety: `old` came from `Arc::into_raw`, and no reader still
+        // needs it to stay alive.
+        unsafe { Arc::from_raw(old) }
+    }
+}
+
+impl<T> Drop for AtomicArc<T> {
+    fn drop(&mut self) {
+        unsafe { drop(Arc::<T>::from_raw(*self.ptr.get_mut())) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Config(usize);
+
+        impl Drop for Config {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let slot = AtomicArc::new(Arc::new(Config(0)));
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..10_000 {
+                        let config = slot.load();
+                        // Versions are published in order.
+                        assert!(config.0 >= last);
+                        last = config.0;
+                    }
+                });
+            }
+            s.spawn(|| {
+                for i in 1..=1000 {
+                    slot.store(Arc::new(Config(i)));
+                }
+            });
+        });
+
+        assert_eq!(slot.load().0, 1000);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1000);
+
+        let current = slot.load();
+        let stale = Arc::new(Config(0));
+        match slot.compare_and_swap(&stale, Arc::new(Config(1))) {
+            Ok(_) => panic!("stale value must not be replaced"),
+            Err(rejected) => assert_eq!(rejected.0, 1),
+        }
+        match slot.compare_and_swap(&current, Arc::new(Config(2))) {
+            Ok(old) => assert!(Arc::ptr_eq(&old, &current)),
+            Err(_) => panic!("current value must be replaced"),
+        }
+        assert_eq!(slot.swap(Arc::new(Config(3))).0, 2);
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+    mem,
+    ops::Deref,
+    ptr::NonNull,
+    sync::{
+        Arc,
+        atomic::{AtomicIsize, Ordering, fence},
+    },
+    thread::{self, ThreadId},
+};
+
+use crate::mutex::Mutex;
+
+/// Set in `ref_count` once the owner's biased count has been merged into it.
+const MERGED: isize = 1;
+/// Set in `ref_count` once a non-owner has queued the value for the owner
+/// to merge.
+const QUEUED: isize = 2;
+/// `ref_count` counts references in steps of four, above the flag bits.
+const ONE: isize = 4;
+
+// `repr(C)` so that a pointer to the header is also a pointer to the whole.
+#[repr(C)]
+struct BiasedData<T> {
+    header: Header,
+    data: T,
+}
+
+struct Header {
+    owner: ThreadId,
+    /// References counted by the owner thread. Only the owner touches it,
+    /// and only until the counts are merged.
+    biased_count: Cell<usize>,
+    /// References counted by all other threads, times `ONE`, plus the flags.
+    /// May be negative while unmerged: a reference cloned on the owner
+    /// thread and dropped on another is subtracted here.
+    ref_count: AtomicIsize,
+    /// Index in the owner's `OWNED` list, so it can be removed without a search.
+    slot: Cell<usize>,
+    /// The owner's `QUEUE`. `None` if the value started out merged.
+    queue: Option<Arc<Queue>>,
+    /// Frees the `BiasedData<T>` this header belongs to.
+    free: unsafe fn(NonNull<Header>),
+}
+
+/// An `Arc` with biased reference counting: clones and drops on the thread
+/// that created it use a plain counter, other threads use the atomic
+/// `ref_count`.
+///
+/// The two counts are merged when the owner drops its last reference, or
+/// when the owner thread exits. Until then only the owner can tell whether
+/// the total has reached zero. So when a non-owner's drop takes `ref_count`
+/// below zero, it queues the value for the owner, which merges it, and frees
+/// it if that was the last reference, on its next `BiasedArc::new` or
+/// `merge_queued`.
+pub struct BiasedArc<T> {
+    ptr: NonNull<BiasedData<T>>,
+    _marker: PhantomData<BiasedData<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for BiasedArc<T> {}
+unsafe impl<T: Send + Sync> Sync for BiasedArc<T> {}
+
+/// Headers of the unmerged values owned by the current thread.
+struct Owned(RefCell<Vec<NonNull<Header>>>);
+
+/// Headers of the values other threads have queued for their owner to merge.
+struct Queue(Mutex<Vec<NonNull<Header>>>);
+
+unsafe impl Send for Queue {}
+unsafe impl Sync for Queue {}
+
+thread_local! {
+    static THREAD_ID: ThreadId = thread::current().id();
+    static OWNED: Owned = const { Owned(RefCell::new(Vec::new())) };
+    static QUEUE: Arc<Queue> = Arc::new(Queue(Mutex::new(Vec::new())));
+    // Set while `OWNED` is being torn down. From then on this thread counts
+    // like any other, so the merge below sees consistent totals.
+    static EXITING: Cell<bool> = const { Cell::new(false) };
+}
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        EXITING.set(true);
+        for header in mem::take(self.0.get_mut()) {
+            unsafe { merge(header) };
+        }
+    }
+}
+
+impl<T> BiasedArc<T> {
+    pub fn new(data: T) -> Self {
+        let owner = current_thread_id();
+        let queue = match owner {
+            Some(_) if !EXITING.get() => QUEUE.try_with(Arc::clone).ok(),
+            _ => None,
+        };
+        if queue.is_some() {
+            merge_queued();
+        }
+        let ptr = NonNull::from(Box::leak(Box::new(BiasedData {
+            header: Header {
+                owner: owner.unwrap_or_else(|| thread::current().id()),
+                biased_count: Cell::new(1),
+                ref_count: AtomicIsize::new(0),
+                slot: Cell::new(0),
+                queue,
+                free: free::<T>,
+            },
+            data,
+        })));
+
+        let header = ptr.cast::<Header>();
+        let registered = unsafe { header.as_ref() }.queue.is_some()
+            && OWNED
+                .try_with(|owned| {
+                    let mut owned = owned.0.borrow_mut();
+                    unsafe { header.as_ref() }.slot.set(owned.len());
+                    owned.push(header);
+                })
+                .is_ok();
+        if !registered {
+            // Nobody would merge it later, so start out merged.
+            let header = unsafe { header.as_ref() };
+            header.biased_count.set(0);
+            header.ref_count.store(ONE | MERGED, Ordering::Relaxed);
+        }
+
+        BiasedArc {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.ptr == b.ptr
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &self.ptr.as_ref().header }
+    }
+
+    /// Whether this thread may use the biased count.
+    fn is_biased(&self) -> bool {
+        let header = self.header();
+        // Only the owner sets `MERGED`, so it always sees its own store.
+        header.ref_count.load(Ordering::Relaxed) & MERGED == 0
+            && current_thread_id() == Some(header.owner)
+            && !EXITING.get()
+    }
+}
+
+impl<T> Deref for BiasedArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &self.ptr.as_ref().data }
+    }
+}
+
+impl<T> Clone for BiasedArc<T> {
+    fn clone(&self) -> Self {
+        let header = self.header();
+        if self.is_biased() {
+            header.biased_count.set(header.biased_count.get() + 1);
+        } else {
+            header.ref_count.fetch_add(ONE, Ordering::Relaxed);
+        }
+        Self {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for BiasedArc<T> {
+    fn drop(&mut self) {
+        let header = self.header();
+        if self.is_biased() {
+            let biased = header.biased_count.get() - 1;
+            header.biased_count.set(biased);
+            // A negative shared count means some of the owner's references
+            // were dropped elsewhere, so ours may have been the last one.
+            // An RMW reads the latest count; if the total is zero, no other
+            // reference exists that could race with us.
+            let last = biased == 0
+                || (header.ref_count.load(Ordering::Relaxed) < 0
+                    && header
+                        .ref_count
+                        .fetch_add(0, Ordering::Relaxed)
+                        .div_euclid(ONE)
+                        + biased as isize
+                        == 0);
+            if last {
+                let header = NonNull::from(header);
+                unregister(header);
+                unsafe { merge(header) };
+            }
+        } else {
+            let mut count = header.ref_count.load(Ordering::Relaxed);
+            let mut queue = None;
+            loop {
+                // The first drop to take an unmerged count below zero queues
+                // the value, under the lock so the owner finds it there once
+                // it sees `QUEUED`.
+                let enqueue = count & (MERGED | QUEUED) == 0 && count < ONE;
+                if enqueue && queue.is_none() {
+                    queue = header.queue.as_ref().map(|queue| queue.0.lock());
+                    count = header.ref_count.load(Ordering::Relaxed);
+                    continue;
+                }
+                let new = count - ONE + if enqueue { QUEUED } else { 0 };
+                match header.ref_count.compare_exchange_weak(
+                    count,
+                    new,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) if enqueue => {
+                        queue.unwrap().push(NonNull::from(header));
+                        return;
+                    }
+                    Ok(_) => break,
+                    Err(current) => count = current,
+                }
+            }
+            drop(queue);
+            if count & !QUEUED == ONE | MERGED {
+                fence(Ordering::Acquire);
+                unsafe { (header.free)(NonNull::from(header)) };
+            }
+        }
+    }
+}
+
+/// Merges the values other threads have queued for the current thread, and
+/// frees those that no reference is left to. `BiasedArc::new` does this too,
+/// but a thread that stops creating values should call it now and then.
+pub fn merge_queued() {
+    let Ok(queued) = QUEUE.try_with(|queue| mem::take(&mut *queue.0.lock())) else {
+        return;
+    };
+    for header in queued {
+        unregister(header);
+        unsafe { merge(header) };
+    }
+}
+
+fn current_thread_id() -> Option<ThreadId> {
+    THREAD_ID.try_with(|id| *id).ok()
+}
+
+/// Removes `header` from the current thread's `OWNED` list.
+fn unregister(header: NonNull<Header>) {
+    // While exiting, the list has already been taken.
+    let _ = OWNED.try_with(|owned| {
+        let mut owned = owned.0.borrow_mut();
+        let slot = unsafe { header.as_ref() }.slot.get();
+        owned.swap_remove(slot);
+        if let Some(moved) = owned.get(slot) {
+            unsafe { moved.as_ref() }.slot.set(slot);
+        }
+    });
+}
+
+/// Moves the biased count into `ref_count` and frees the value if that
+/// leaves no references. Must be called on the owner thread, at most once.
+unsafe fn merge(header: NonNull<Header>) {
+    let h = unsafe { header.as_ref() };
+    let biased = h.biased_count.replace(0) as isize;
+    // Release for our own uses of the data, Acquire for the other threads'
+    // uses in case we free it.
+    let old = h
+        .ref_count
+        .fetch_add(biased * ONE + MERGED, Ordering::AcqRel);
+    if old & QUEUED != 0 {
+        // Unless `merge_queued` has taken it out already.
+        if let Some(queue) = &h.queue {
+            queue.0.lock().retain(|&queued| queued != header);
+        }
+    }
+    if old.div_euclid(ONE) + biased == 0 {
+        unsafe { (h.free)(header) };
+    }
+}
+
+unsafe fn free<T>(header: NonNull<Header>) {
+    unsafe { drop(Box::from_raw(header.cast::<BiasedData<T>>().as_ptr())) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct DectorDrop(&'static str);
+
+        impl Drop for DectorDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        // Owner-only traffic never touches the atomic count.
+        let a = BiasedArc::new(DectorDrop("a"));
+        let b = a.clone();
+        assert_eq!(a.header().biased_count.get(), 2);
+        assert_eq!(a.header().ref_count.load(Ordering::Relaxed), 0);
+        drop(b);
+        drop(a);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+
+        // Another thread clones through the atomic count and drops last.
+        let a = BiasedArc::new(DectorDrop("b"));
+        let b = a.clone();
+        let t = thread::spawn(move || {
+            let c = b.clone();
+            assert_eq!(c.0, "b");
+            c
+        });
+        let c = t.join().unwrap();
+        drop(a);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        drop(c);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+
+        // An owner reference dropped elsewhere is caught up with by the owner.
+        let a = BiasedArc::new(DectorDrop("c"));
+        let b = a.clone();
+        thread::spawn(move || drop(b)).join().unwrap();
+        drop(a);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+
+        // The owner's only reference dropped elsewhere is queued for the
+        // owner, which frees it when it merges.
+        let a = BiasedArc::new(DectorDrop("e"));
+        let b = a.clone();
+        thread::spawn(move || drop((a, b))).join().unwrap();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+        merge_queued();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 4);
+
+        // Queued, then dropped by the owner before it merges.
+        let a = BiasedArc::new(DectorDrop("f"));
+        let b = a.clone();
+        thread::spawn(move || drop(b)).join().unwrap();
+        drop(a);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 5);
+        merge_queued();
+        let a = BiasedArc::new(DectorDrop("g"));
+        thread::spawn(move || drop(a)).join().unwrap();
+        drop(BiasedArc::new(()));
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 6);
+
+        // Values still owned by an exiting thread are merged on exit.
+        let a = thread::spawn(|| {
+            let a = BiasedArc::new(DectorDrop("d"));
+            let _b = a.clone();
+            a
+        })
+        .join()
+        .unwrap();
+        assert_eq!(a.0, "d");
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 6);
+        drop(a);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 7);
+    }
+}
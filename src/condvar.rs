@@ -35,3 +35,9 @@ impl Condvar {
         lock.lock()
     }
 }
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
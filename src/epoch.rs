@@ -0,0 +1,314 @@
+use std::{
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+    mem,
+    sync::atomic::{AtomicUsize, Ordering, fence},
+};
+
+use crate::{arc::Arc, mutex::Mutex};
+
+// Epochs are counted in steps of two, the lowest bit of a local epoch
+// marks the thread as pinned.
+const PINNED: usize = 1;
+const STEP: usize = 2;
+
+/// Number of deferred functions a thread collects before sealing its bag.
+const BAG_CAPACITY: usize = 64;
+/// Number of pins between attempts to advance the epoch and collect garbage.
+const PINS_BETWEEN_COLLECT: usize = 128;
+
+/// A thread `pin`s itself before reading shared pointers and stays pinned for
+/// as long as it holds the returned `Guard`. Memory that has been unlinked is
+/// handed to `Guard::defer_destroy` instead of being freed right away. It
+/// collects in a per-thread bag, which is sealed with the global epoch once
+/// full. The global epoch only advances when every pinned thread has caught up
+/// with it, so once it has advanced twice past a bag's epoch, no thread can
+/// still be reading anything in that bag.
+struct Global {
+    epoch: AtomicUsize,
+    locals: Mutex<Vec<Arc<Local>>>,
+    garbage: Mutex<Vec<SealedBag>>,
+}
+
+static GLOBAL: Global = Global {
+    epoch: AtomicUsize::new(0),
+    locals: Mutex::new(Vec::new()),
+    garbage: Mutex::new(Vec::new()),
+};
+
+/// The part of a thread's state that other threads look at.
+struct Local {
+    /// The epoch this thread is pinned in, with `PINNED` set, or 0.
+    epoch: AtomicUsize,
+}
+
+/// A function to run once no pinned thread can be using its data.
+struct Deferred {
+    call: unsafe fn(*mut u8),
+    data: *mut u8,
+}
+
+// Whoever defers a function promises it may run on any thread.
+unsafe impl Send for Deferred {}
+
+struct SealedBag {
+    epoch: usize,
+    deferred: Vec<Deferred>,
+}
+
+struct Handle {
+    local: Arc<Local>,
+    bag: RefCell<Vec<Deferred>>,
+    guard_count: Cell<usize>,
+    pin_count: Cell<usize>,
+}
+
+thread_local! {
+    static HANDLE: Handle = Handle::register();
+}
+
+/// Keeps the current thread pinned. Pointers loaded while a `Guard` is alive
+/// stay valid until it's dropped, even if another thread unlinks and defers
+/// them in the meantime.
+pub struct Guard {
+    // Pinning is per thread.
+    _not_send: PhantomData<*mut ()>,
+}
+
+/// Pins the current thread. Guards nest, the thread stays pinned until the
+/// last one is dropped.
+pub fn pin() -> Guard {
+    HANDLE.with(Handle::pin);
+    Guard {
+        _not_send: PhantomData,
+    }
+}
+
+/// Whether the current thread holds a `Guard`.
+pub fn is_pinned() -> bool {
+    HANDLE.with(|handle| handle.guard_count.get() > 0)
+}
+
+impl Guard {
+    /// Drops `ptr` as a `Box<T>` once no pinned thread can still see it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Box::into_raw`, must already be unreachable for
+    /// threads pinning from now on, and `T` must be fine to drop on any thread.
+    pub unsafe fn defer_destroy<T>(&self, ptr: *mut T) {
+        unsafe fn drop_box<T>(data: *mut u8) {
+            unsafe { drop(Box::from_raw(data.cast::<T>())) }
+        }
+        unsafe { self.defer_unchecked(ptr.cast(), drop_box::<T>) }
+    }
+
+    /// Calls `call(data)` once no pinned thread can still see `data`.
+    /// Unlike `defer`, this doesn't allocate.
+    ///
+    /// # Safety
+    ///
+    /// `data` must already be unreachable for threads pinning from now on,
+    /// and `call(data)` must be fine to run on any thread, at any later time.
+    pub unsafe fn defer_unchecked(&self, data: *mut u8, call: unsafe fn(*mut u8)) {
+        HANDLE.with(|handle| handle.defer(Deferred { call, data }));
+    }
+
+    /// Runs `f` once no thread is pinned in the current epoch anymore.
+    pub fn defer<F: FnOnce() + Send + 'static>(&self, f: F) {
+        unsafe fn call_box<F: FnOnce()>(data: *mut u8) {
+            let f = unsafe { Box::from_raw(data.cast::<F>()) };
+            f();
+        }
+        unsafe { self.defer_unchecked(Box::into_raw(Box::new(f)).cast(), call_box::<F>) }
+    }
+
+    /// Hands the current thread's bag to the global garbage and collects
+    /// whatever has become safe to free.
+    pub fn flush(&self) {
+        HANDLE.with(|handle| {
+            handle.seal_bag();
+            collect();
+        });
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        HANDLE.with(Handle::unpin);
+    }
+}
+
+impl Handle {
+    fn register() -> Self {
+        let local = Arc::new(Local {
+            epoch: AtomicUsize::new(0),
+        });
+        GLOBAL.locals.lock().push(local.clone());
+        Handle {
+            local,
+            bag: RefCell::new(Vec::new()),
+            guard_count: Cell::new(0),
+            pin_count: Cell::new(0),
+        }
+    }
+
+    fn pin(&self) {
+        let count = self.guard_count.get();
+        self.guard_count.set(count + 1);
+        if count > 0 {
+            return;
+        }
+
+        let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+        self.local.epoch.store(epoch | PINNED, Ordering::Relaxed);
+        // The pin has to be visible to `try_advance` before we read any
+        // shared pointer, which is a store followed by loads, so only SeqCst
+        // will do.
+        fence(Ordering::SeqCst);
+
+        let pins = self.pin_count.get().wrapping_add(1);
+        self.pin_count.set(pins);
+        if pins.is_multiple_of(PINS_BETWEEN_COLLECT) {
+            collect();
+        }
+    }
+
+    fn unpin(&self) {
+        let count = self.guard_count.get() - 1;
+        self.guard_count.set(count);
+        if count == 0 {
+            // Release so our reads of shared memory happen before anything
+            // `try_advance` lets be freed afterwards.
+            self.local.epoch.store(0, Ordering::Release);
+        }
+    }
+
+    fn defer(&self, deferred: Deferred) {
+        let full = {
+            let mut bag = self.bag.borrow_mut();
+            bag.push(deferred);
+            bag.len() >= BAG_CAPACITY
+        };
+        if full {
+            self.seal_bag();
+            collect();
+        }
+    }
+
+    fn seal_bag(&self) {
+        let deferred = mem::take(&mut *self.bag.borrow_mut());
+        if deferred.is_empty() {
+            return;
+        }
+        // Everything in the bag was unlinked before this load, so threads
+        // pinned from the epoch after this one can't have seen any of it.
+        fence(Ordering::SeqCst);
+        let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+        GLOBAL.garbage.lock().push(SealedBag { epoch, deferred });
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.seal_bag();
+        GLOBAL
+            .locals
+            .lock()
+            .retain(|local| !Arc::ptr_eq(local, &self.local));
+    }
+}
+
+/// Advances the global epoch if every pinned thread has seen the current one.
+/// Returns the global epoch.
+fn try_advance() -> usize {
+    let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+    fence(Ordering::SeqCst);
+
+    for local in GLOBAL.locals.lock().iter() {
+        let local_epoch = local.epoch.load(Ordering::Relaxed);
+        if local_epoch & PINNED != 0 && local_epoch & !PINNED != epoch {
+            return epoch;
+        }
+    }
+    // Acquire to match the Release in `unpin`.
+    fence(Ordering::Acquire);
+
+    let next = epoch.wrapping_add(STEP);
+    match GLOBAL
+        .epoch
+        .compare_exchange(epoch, next, Ordering::Release, Ordering::Relaxed)
+    {
+        Ok(_) => next,
+        Err(current) => current,
+    }
+}
+
+/// Runs the deferred functions of every bag the epoch has moved two steps past.
+fn collect() {
+    let epoch = try_advance();
+
+    let ready: Vec<SealedBag> = GLOBAL
+        .garbage
+        .lock()
+        .extract_if(.., |bag| epoch.wrapping_sub(bag.epoch) >= 2 * STEP)
+        .collect();
+
+    // Outside the lock, as the functions may defer more work themselves.
+    for bag in ready {
+        for deferred in bag.deferred {
+            unsafe { (deferred.call)(deferred.data) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        static NUM_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+        let (pinned_tx, pinned_rx) = mpsc::channel();
+        let (unpin_tx, unpin_rx) = mpsc::channel::<()>();
+        let reader = thread::spawn(move || {
+            let guard = pin();
+            assert!(is_pinned());
+            pinned_tx.send(()).unwrap();
+            unpin_rx.recv().unwrap();
+            drop(guard);
+            assert!(!is_pinned());
+        });
+        pinned_rx.recv().unwrap();
+
+        let guard = pin();
+        guard.defer(|| {
+            NUM_RUNS.fetch_add(1, Ordering::Relaxed);
+        });
+        let value = Box::into_raw(Box::new(String::from("unlinked")));
+        unsafe { guard.defer_destroy(value) };
+        drop(guard);
+
+        // The reader may still see what we deferred.
+        for _ in 0..10 {
+            pin().flush();
+        }
+        assert_eq!(NUM_RUNS.load(Ordering::Relaxed), 0);
+
+        unpin_tx.send(()).unwrap();
+        reader.join().unwrap();
+
+        // Other tests may be pinned too, so keep trying for a while.
+        for _ in 0..1_000_000 {
+            if NUM_RUNS.load(Ordering::Relaxed) == 1 {
+                break;
+            }
+            pin().flush();
+            thread::yield_now();
+        }
+        assert_eq!(NUM_RUNS.load(Ordering::Relaxed), 1);
+    }
+}
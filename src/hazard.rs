@@ -0,0 +1,295 @@
+use std::{
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+    mem,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering, fence},
+};
+
+use crate::mutex::Mutex;
+
+/// Number of hazard pointers a single thread can hold at once.
+const SLOTS: usize = 8;
+/// Number of retired pointers a thread collects before scanning the hazards.
+const RETIRE_THRESHOLD: usize = 64;
+
+/// Hazard pointers: before dereferencing a shared pointer, a thread publishes
+/// it in one of its slots and checks that it's still reachable. A retired
+/// pointer is only freed once a scan of all slots doesn't find it. Unlike
+/// with epochs, a stalled reader only holds on to the few nodes it protects,
+/// so the amount of unfreed garbage stays bounded.
+struct Global {
+    /// Records are never freed, only handed to the next thread when their
+    /// owner exits, so they can be borrowed for `'static`.
+    records: Mutex<Vec<&'static Record>>,
+    /// Retired pointers left behind by exited threads.
+    orphans: Mutex<Vec<Retired>>,
+}
+
+static GLOBAL: Global = Global {
+    records: Mutex::new(Vec::new()),
+    orphans: Mutex::new(Vec::new()),
+};
+
+/// The hazard slots of one thread.
+struct Record {
+    in_use: AtomicBool,
+    hazards: [AtomicPtr<u8>; SLOTS],
+}
+
+/// A pointer waiting to be dropped.
+struct Retired {
+    ptr: *mut u8,
+    drop: unsafe fn(*mut u8),
+}
+
+// Whoever retires a pointer promises it may be dropped on any thread.
+unsafe impl Send for Retired {}
+
+struct Handle {
+    record: &'static Record,
+    /// Bit `i` is set while slot `i` is free.
+    free_slots: Cell<u32>,
+    retired: RefCell<Vec<Retired>>,
+}
+
+thread_local! {
+    static HANDLE: Handle = Handle::register();
+}
+
+/// One of the current thread's hazard slots. Whatever it protects can't be
+/// freed by `retire` until it protects something else or is dropped.
+pub struct HazardPointer {
+    slot: &'static AtomicPtr<u8>,
+    index: usize,
+    // Slots belong to a thread.
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl HazardPointer {
+    /// Takes a free slot of the current thread.
+    ///
+    /// Panics if the thread already holds all of its slots.
+    pub fn new() -> Self {
+        HANDLE.with(|handle| {
+            let free = handle.free_slots.get();
+            assert!(free != 0, "too many hazard pointers on one thread");
+            let index = free.trailing_zeros() as usize;
+            handle.free_slots.set(free & !(1 << index));
+            HazardPointer {
+                slot: &handle.record.hazards[index],
+                index,
+                _not_send: PhantomData,
+            }
+        })
+    }
+
+    /// Loads `src` and protects the pointer it holds.
+    ///
+    /// The returned pointer stays valid until this hazard pointer is reset,
+    /// dropped or used to protect something else, provided whoever unlinks it
+    /// from `src` frees it through `retire`.
+    pub fn protect<T>(&mut self, src: &AtomicPtr<T>) -> *mut T {
+        self.protect_with(|| src.load(Ordering::Acquire))
+    }
+
+    /// Like `protect`, for pointers that aren't stored in an `AtomicPtr`.
+    /// Calls `load` until it returns the same pointer twice in a row, with
+    /// the hazard published in between.
+    pub fn protect_with<T>(&mut self, mut load: impl FnMut() -> *mut T) -> *mut T {
+        let mut ptr = load();
+        loop {
+            self.slot.store(ptr.cast(), Ordering::Relaxed);
+            // The hazard has to be visible to `scan` before we check that the
+            // pointer is still reachable, a store followed by a load.
+            fence(Ordering::SeqCst);
+            let current = load();
+            if current == ptr {
+                return ptr;
+            }
+            ptr = current;
+        }
+    }
+
+    /// Stops protecting anything.
+    pub fn reset(&mut self) {
+        // Release so our reads happen before a `scan` that frees the pointer.
+        self.slot.store(std::ptr::null_mut(), Ordering::Release);
+    }
+}
+
+impl Default for HazardPointer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HazardPointer {
+    fn drop(&mut self) {
+        self.reset();
+        let _ = HANDLE.try_with(|handle| {
+            handle
+                .free_slots
+                .set(handle.free_slots.get() | (1 << self.index));
+        });
+    }
+}
+
+/// Drops `ptr` as a `Box<T>` once no hazard pointer protects it.
+///
+/// # Safety
+///
+/// `ptr` must come from `Box::into_raw`, must already be unreachable for
+/// threads protecting from now on, and `T` must be fine to drop on any thread.
+pub unsafe fn retire<T>(ptr: *mut T) {
+    unsafe fn drop_box<T>(ptr: *mut u8) {
+        unsafe { drop(Box::from_raw(ptr.cast::<T>())) }
+    }
+    unsafe { retire_with(ptr.cast(), drop_box::<T>) }
+}
+
+/// Calls `drop(ptr)` once no hazard pointer protects `ptr`.
+///
+/// # Safety
+///
+/// `ptr` must already be unreachable for threads protecting from now on, and
+/// `drop(ptr)` must be fine to run on any thread, at any later time.
+pub unsafe fn retire_with(ptr: *mut u8, drop: unsafe fn(*mut u8)) {
+    HANDLE.with(|handle| {
+        let full = {
+            let mut list = handle.retired.borrow_mut();
+            list.push(Retired { ptr, drop });
+            list.len() >= RETIRE_THRESHOLD
+        };
+        if full {
+            handle.scan();
+        }
+    });
+}
+
+/// Frees whatever the current thread has retired that is no longer protected.
+pub fn flush() {
+    HANDLE.with(Handle::scan);
+}
+
+impl Handle {
+    fn register() -> Self {
+        let mut records = GLOBAL.records.lock();
+        let record = match records.iter().find(|record| {
+            record
+                .in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        }) {
+            Some(record) => record,
+            None => {
+                let record: &'static Record = Box::leak(Box::new(Record {
+                    in_use: AtomicBool::new(true),
+                    hazards: Default::default(),
+                }));
+                records.push(record);
+                record
+            }
+        };
+        Handle {
+            record,
+            free_slots: Cell::new((1 << SLOTS) - 1),
+            retired: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn scan(&self) {
+        let mut retired = mem::take(&mut *self.retired.borrow_mut());
+        retired.append(&mut GLOBAL.orphans.lock());
+
+        // Everything retired was unlinked before this point, so a thread
+        // that protects one of them after the fence will fail to validate it.
+        fence(Ordering::SeqCst);
+        let mut hazards: Vec<*mut u8> = GLOBAL
+            .records
+            .lock()
+            .iter()
+            .flat_map(|record| &record.hazards)
+            .map(|hazard| hazard.load(Ordering::Acquire))
+            .filter(|ptr| !ptr.is_null())
+            .collect();
+        hazards.sort_unstable();
+
+        let (keep, free): (Vec<_>, Vec<_>) = retired
+            .into_iter()
+            .partition(|retired| hazards.binary_search(&retired.ptr).is_ok());
+        self.retired.borrow_mut().extend(keep);
+        // Outside the borrow, as dropping may retire more.
+        for retired in free {
+            unsafe { (retired.drop)(retired.ptr) };
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // No scan: dropping a retired pointer may need this thread's handle,
+        // which is gone by now. The next scan on another thread frees them.
+        GLOBAL.orphans.lock().append(self.retired.get_mut());
+        for hazard in &self.record.hazards {
+            hazard.store(std::ptr::null_mut(), Ordering::Release);
+        }
+        self.record.in_use.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct DectorDrop(usize);
+
+        impl Drop for DectorDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let shared = AtomicPtr::new(Box::into_raw(Box::new(DectorDrop(1))));
+
+        thread::scope(|s| {
+            let (protected_tx, protected_rx) = mpsc::channel();
+            let (release_tx, release_rx) = mpsc::channel::<()>();
+            let shared = &shared;
+            s.spawn(move || {
+                let mut hp = HazardPointer::new();
+                let ptr = hp.protect(shared);
+                protected_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                // Still valid, even though it has been retired.
+                assert_eq!(unsafe { (*ptr).0 }, 1);
+                drop(hp);
+            });
+            protected_rx.recv().unwrap();
+
+            let old = shared.swap(Box::into_raw(Box::new(DectorDrop(2))), Ordering::AcqRel);
+            unsafe { retire(old) };
+            flush();
+            assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+
+            release_tx.send(()).unwrap();
+        });
+
+        flush();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+
+        // Going over the threshold scans by itself.
+        for i in 0..RETIRE_THRESHOLD {
+            unsafe { retire(Box::into_raw(Box::new(DectorDrop(i)))) };
+        }
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1 + RETIRE_THRESHOLD);
+
+        unsafe { drop(Box::from_raw(shared.into_inner())) };
+    }
+}
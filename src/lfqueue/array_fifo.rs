@@ -0,0 +1,194 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use super::fifo::CachePadded;
+
+/// The SPSC ring from `fifo`, with its buffer inline instead of on the heap.
+///
+///  - `N` must be a power of two, so a position is turned into an index with
+///    a mask rather than a `%`. It's checked when `new` is compiled.
+///  - Positions are `usize` and wrap around, which the power of two capacity
+///    makes harmless, so it also works where there's no `AtomicU64`.
+///  - `new` is `const`, so a `Fifo` can be a `static`, and `split` hands out
+///    the two ends as borrows of it, with no `Arc`.
+pub struct Fifo<T, const N: usize> {
+    buffer: [UnsafeCell<MaybeUninit<T>>; N],
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    split: AtomicBool,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for Fifo<T, N> {}
+
+pub struct Producer<'a, T, const N: usize> {
+    fifo: &'a Fifo<T, N>,
+    head: usize,
+    // Local cache of the consumer's tail to reduce atomic loads
+    cache_tail: usize,
+}
+
+pub struct Consumer<'a, T, const N: usize> {
+    fifo: &'a Fifo<T, N>,
+    tail: usize,
+    // Local cache of the producer's head to reduce atomic loads
+    cache_head: usize,
+}
+
+unsafe impl<T: Send, const N: usize> Send for Producer<'_, T, N> {}
+unsafe impl<T: Send, const N: usize> Send for Consumer<'_, T, N> {}
+
+impl<T, const N: usize> Fifo<T, N> {
+    const MASK: usize = {
+        assert!(N.is_power_of_two(), "capacity must be a power of two");
+        N - 1
+    };
+
+    pub const fn new() -> Self {
+        // Fails the build for a bad `N`.
+        let _ = Self::MASK;
+        Fifo {
+            buffer: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// The producer and consumer ends. There's only one of each, so this
+    /// returns `None` if the fifo has been split before.
+    pub fn split(&self) -> Option<(Producer<'_, T, N>, Consumer<'_, T, N>)> {
+        if self.split.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        Some((
+            Producer {
+                fifo: self,
+                head: 0,
+                cache_tail: 0,
+            },
+            Consumer {
+                fifo: self,
+                tail: 0,
+                cache_head: 0,
+            },
+        ))
+    }
+
+    fn slot(&self, pos: usize) -> *mut MaybeUninit<T> {
+        unsafe { self.buffer.get_unchecked(pos & Self::MASK).get() }
+    }
+}
+
+impl<T, const N: usize> Default for Fifo<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Producer<'_, T, N> {
+    /// Pushes `value`, or hands it back if the ring is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.head.wrapping_sub(self.cache_tail) == N {
+            // Update local cache of tail
+            self.cache_tail = self.fifo.tail.0.load(Ordering::Acquire);
+
+            if self.head.wrapping_sub(self.cache_tail) == N {
+                return Err(value);
+            }
+        }
+
+        unsafe { (*self.fifo.slot(self.head)).write(value) };
+        self.head = self.head.wrapping_add(1);
+        self.fifo.head.0.store(self.head, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+    pub fn pop(&mut self) -> Option<T> {
+        if self.tail == self.cache_head {
+            // Update local cache of head
+            self.cache_head = self.fifo.head.0.load(Ordering::Acquire);
+
+            if self.tail == self.cache_head {
+                return None;
+            }
+        }
+
+        let value = unsafe { (*self.fifo.slot(self.tail)).assume_init_read() };
+        self.tail = self.tail.wrapping_add(1);
+        self.fifo.tail.0.store(self.tail, Ordering::Release);
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Drop for Fifo<T, N> {
+    fn drop(&mut self) {
+        let (tail, head) = (*self.tail.0.get_mut(), *self.head.0.get_mut());
+        let mut pos = tail;
+        while pos != head {
+            unsafe { (*self.slot(pos)).assume_init_drop() };
+            pos = pos.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        let fifo = Fifo::<String, 2>::new();
+        let (mut producer, mut consumer) = fifo.split().unwrap();
+        assert!(fifo.split().is_none());
+
+        assert_eq!(consumer.pop(), None);
+        for i in 0..5 {
+            producer.push(i.to_string()).unwrap();
+            assert_eq!(consumer.pop(), Some(i.to_string()));
+        }
+        producer.push("a".to_string()).unwrap();
+        producer.push("b".to_string()).unwrap();
+        assert_eq!(producer.push("c".to_string()), Err("c".to_string()));
+        assert_eq!(consumer.pop(), Some("a".to_string()));
+        producer.push("c".to_string()).unwrap();
+        // "b" and "c" are dropped with the fifo.
+    }
+
+    #[test]
+    fn test_static() {
+        static FIFO: Fifo<usize, 16> = Fifo::new();
+        const N: usize = 10_000;
+
+        let (mut producer, mut consumer) = FIFO.split().unwrap();
+        let t = thread::spawn(move || {
+            for i in 0..N {
+                let mut value = i;
+                while let Err(v) = producer.push(value) {
+                    value = v;
+                    thread::yield_now();
+                }
+            }
+        });
+        for i in 0..N {
+            loop {
+                if let Some(value) = consumer.pop() {
+                    assert_eq!(value, i);
+                    break;
+                }
+                thread::yield_now();
+            }
+        }
+        t.join().unwrap();
+        assert_eq!(consumer.pop(), None);
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use super::fifo::CachePadded;
+use crate::mutex::Mutex;
+
+/// A Disruptor-style broadcast ring: one producer, and any number of
+/// consumers that each see every value.
+///
+///  - `head` works as in `fifo`. Instead of one `tail`, every consumer
+///    publishes its own cursor, and the producer may only reuse a slot once
+///    the slowest cursor has moved past it.
+///  - The producer caches the slowest cursor and only goes through the list
+///    again when the cache says the ring is full.
+///  - A new consumer starts at or after a position the producer already
+///    waits for, so registering it never invalidates that cache.
+struct Shared<T> {
+    buffer: Vec<UnsafeCell<MaybeUninit<T>>>,
+    capacity: usize,
+    head: CachePadded<AtomicU64>,
+    cursors: Mutex<Vec<Arc<CachePadded<AtomicU64>>>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for Shared<T> {}
+unsafe impl<T: Send + Sync> Send for Shared<T> {}
+
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    head: u64,
+    // Local cache of the slowest consumer's cursor to reduce atomic loads
+    cache_min: u64,
+}
+
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+    cursor: Arc<CachePadded<AtomicU64>>,
+    tail: u64,
+    // Local cache of producer's head to reduce atomic loads
+    cache_head: u64,
+}
+
+pub fn new<T: Send + Sync>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0);
+
+    let buffer = (0..capacity)
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let shared = Arc::new(Shared {
+        buffer,
+        capacity,
+        head: CachePadded(AtomicU64::new(0)),
+        cursors: Mutex::new(Vec::new()),
+    });
+    let producer = Producer {
+        shared,
+        head: 0,
+        cache_min: 0,
+    };
+    let consumer = producer.subscribe();
+
+    (producer, consumer)
+}
+
+impl<T> Shared<T> {
+    fn slot(&self, pos: u64) -> *mut MaybeUninit<T> {
+        let index = (pos % self.capacity as u64) as usize;
+        unsafe { self.buffer.get_unchecked(index).get() }
+    }
+
+    /// Adds a consumer that reads from `pos` on.
+    fn register(self: &Arc<Self>, pos: u64) -> Consumer<T> {
+        let cursor = Arc::new(CachePadded(AtomicU64::new(pos)));
+        self.cursors.lock().push(cursor.clone());
+        Consumer {
+            shared: self.clone(),
+            cursor,
+            tail: pos,
+            cache_head: pos,
+        }
+    }
+}
+
+impl<T: Send + Sync> Producer<T> {
+    /// Pushes `value`, or hands it back if the slowest consumer is a whole
+    /// ring behind.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        let capacity = self.shared.capacity as u64;
+        if self.head - self.cache_min == capacity {
+            // Update local cache of the slowest cursor. Without consumers,
+            // nothing holds the producer back.
+            self.cache_min = self
+                .shared
+                .cursors
+                .lock()
+                .iter()
+                .map(|cursor| cursor.0.load(Ordering::Acquire))
+                .min()
+                .unwrap_or(self.head);
+
+            if self.head - self.cache_min == capacity {
+                return Err(value);
+            }
+        }
+
+        let slot = self.shared.slot(self.head);
+        // Every consumer is done with the value from one lap ago.
+        if self.head >= capacity {
+            unsafe { (*slot).assume_init_drop() };
+        }
+        unsafe { (*slot).write(value) };
+        self.head += 1;
+        self.shared.head.0.store(self.head, Ordering::Release);
+        Ok(())
+    }
+
+    /// A new consumer, which sees values pushed from now on.
+    pub fn subscribe(&self) -> Consumer<T> {
+        self.shared.register(self.head)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+}
+
+impl<T: Send + Sync> Consumer<T> {
+    /// Clones the next value, if the producer has pushed it.
+    pub fn try_recv(&mut self) -> Option<T>
+    where
+        T: Clone,
+    {
+        if self.tail == self.cache_head {
+            // Update local cache of head
+            self.cache_head = self.shared.head.0.load(Ordering::Acquire);
+
+            if self.tail == self.cache_head {
+                return None;
+            }
+        }
+
+        let value = unsafe { (*self.shared.slot(self.tail)).assume_init_ref().clone() };
+        self.tail += 1;
+        // Release so our read is done before the producer reuses the slot.
+        self.cursor.0.store(self.tail, Ordering::Release);
+        Some(value)
+    }
+}
+
+/// Another consumer, at the same position as this one.
+impl<T> Clone for Consumer<T> {
+    fn clone(&self) -> Self {
+        self.shared.register(self.tail)
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        // The producer stops waiting for us the next time it looks.
+        self.shared
+            .cursors
+            .lock()
+            .retain(|cursor| !Arc::ptr_eq(cursor, &self.cursor));
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // Every slot written in the last lap still holds its value.
+        let head = *self.head.0.get_mut();
+        for pos in head.saturating_sub(self.capacity as u64)..head {
+            unsafe { (*self.slot(pos)).assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        let (mut producer, mut a) = new::<String>(2);
+        let mut b = a.clone();
+        assert_eq!(a.try_recv(), None);
+
+        producer.try_push("x".to_string()).unwrap();
+        producer.try_push("y".to_string()).unwrap();
+        assert_eq!(producer.try_push("z".to_string()), Err("z".to_string()));
+
+        // Both have to get past "x" before it's overwritten.
+        assert_eq!(a.try_recv().as_deref(), Some("x"));
+        assert_eq!(producer.try_push("z".to_string()), Err("z".to_string()));
+        assert_eq!(b.try_recv().as_deref(), Some("x"));
+        producer.try_push("z".to_string()).unwrap();
+
+        // A clone starts where its original is, a subscriber at the head.
+        let mut c = a.clone();
+        let mut d = producer.subscribe();
+        assert_eq!(c.try_recv().as_deref(), Some("y"));
+        assert_eq!(d.try_recv(), None);
+
+        // A dropped consumer no longer holds the producer back.
+        assert_eq!(a.try_recv().as_deref(), Some("y"));
+        assert_eq!(producer.try_push("w".to_string()), Err("w".to_string()));
+        drop(b);
+        producer.try_push("w".to_string()).unwrap();
+        assert_eq!(d.try_recv().as_deref(), Some("w"));
+        // "z" and "w" are dropped with the ring.
+    }
+
+    #[test]
+    fn test_concurrent() {
+        const N: u64 = 10_000;
+
+        let (mut producer, consumer) = new::<u64>(16);
+        let consumers: Vec<_> = (0..3)
+            .map(|_| {
+                let mut consumer = consumer.clone();
+                thread::spawn(move || {
+                    for i in 0..N {
+                        loop {
+                            if let Some(value) = consumer.try_recv() {
+                                assert_eq!(value, i);
+                                break;
+                            }
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(consumer);
+
+        for i in 0..N {
+            while producer.try_push(i).is_err() {
+                thread::yield_now();
+            }
+        }
+        for consumer in consumers {
+            consumer.join().unwrap();
+        }
+    }
+}
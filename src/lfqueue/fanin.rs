@@ -0,0 +1,282 @@
+use std::{
+    mem::ManuallyDrop,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering, fence},
+    },
+};
+
+use super::fifo::{self, Waiter};
+pub use super::fifo::{Disconnected, PopError, PushError};
+use crate::mutex::Mutex;
+
+/// An MPSC queue made of one `fifo` lane per sender, so senders never
+/// contend with each other, only with the receiver on their own lane.
+///
+///  - Cloning a `Sender` registers a new lane. Its consumer end waits in
+///    `pending` until the receiver picks it up on its next poll.
+///  - The receiver polls its lanes round-robin, and drops a lane once it's
+///    drained and its sender is gone.
+///  - One `Waiter` for the whole queue, woken by every send, lets `recv`
+///    sleep until any lane has something.
+struct Shared<T: Send> {
+    lane_capacity: usize,
+    pending: Mutex<Vec<fifo::Consumer<T>>>,
+    has_pending: AtomicBool,
+    /// Set once the receiver is gone, so new lanes are closed right away.
+    closed: AtomicBool,
+    waiter: Waiter,
+}
+
+pub struct Sender<T: Send> {
+    shared: Arc<Shared<T>>,
+    // Dropped by hand, before waking the receiver.
+    lane: ManuallyDrop<fifo::Producer<T>>,
+}
+
+pub struct Receiver<T: Send> {
+    shared: Arc<Shared<T>>,
+    lanes: Vec<fifo::Consumer<T>>,
+    // The lane to poll first.
+    next: usize,
+}
+
+/// Every sender gets a lane with room for `lane_capacity` values.
+pub fn new<T: Send>(lane_capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        lane_capacity,
+        pending: Mutex::new(Vec::new()),
+        has_pending: AtomicBool::new(false),
+        closed: AtomicBool::new(false),
+        waiter: Waiter::new(),
+    });
+    let receiver = Receiver {
+        shared: shared.clone(),
+        lanes: Vec::new(),
+        next: 0,
+    };
+    (Sender::register(shared), receiver)
+}
+
+impl<T: Send> Sender<T> {
+    fn register(shared: Arc<Shared<T>>) -> Self {
+        let (producer, consumer) = fifo::new(shared.lane_capacity);
+        {
+            let mut pending = shared.pending.lock();
+            if !shared.closed.load(Ordering::Relaxed) {
+                pending.push(consumer);
+                // SeqCst, as `recv` checks it after going to sleep.
+                shared.has_pending.store(true, Ordering::SeqCst);
+            }
+        }
+        Sender {
+            shared,
+            lane: ManuallyDrop::new(producer),
+        }
+    }
+
+    /// Sends `value`, or hands it back if this sender's lane is full or the
+    /// receiver is gone.
+    pub fn try_send(&mut self, value: T) -> Result<(), PushError<T>> {
+        self.lane.try_push(value)?;
+        self.shared.waiter.wake();
+        Ok(())
+    }
+
+    /// Sends `value`, sleeping while this sender's lane is full.
+    pub fn send(&mut self, value: T) -> Result<(), Disconnected<T>> {
+        self.lane.push_blocking(value)?;
+        self.shared.waiter.wake();
+        Ok(())
+    }
+}
+
+/// Another sender, with a lane of its own.
+impl<T: Send> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender::register(self.shared.clone())
+    }
+}
+
+impl<T: Send> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Marks the lane disconnected, which the receiver has to notice.
+        unsafe { ManuallyDrop::drop(&mut self.lane) };
+        self.shared.waiter.wake();
+    }
+}
+
+impl<T: Send> Receiver<T> {
+    /// Takes a value from the next lane that has one. `Disconnected` once
+    /// every sender is gone and every lane is drained.
+    pub fn try_recv(&mut self) -> Result<T, PopError> {
+        self.adopt();
+        let mut i = self.next;
+        let mut polled = 0;
+        while polled < self.lanes.len() {
+            if i >= self.lanes.len() {
+                i = 0;
+            }
+            match self.lanes[i].try_pop() {
+                Ok(value) => {
+                    self.next = i + 1;
+                    return Ok(value);
+                }
+                Err(PopError::Empty) => {
+                    i += 1;
+                    polled += 1;
+                }
+                // Its sender is gone and it's drained. `i` is the next lane now.
+                Err(PopError::Disconnected) => drop(self.lanes.remove(i)),
+            }
+        }
+        // A sender may have registered another lane before its own went away.
+        if self.lanes.is_empty() {
+            self.adopt();
+            if self.lanes.is_empty() {
+                return Err(PopError::Disconnected);
+            }
+        }
+        Err(PopError::Empty)
+    }
+
+    /// Takes a value from the next lane that has one, sleeping while they're
+    /// all empty.
+    pub fn recv(&mut self) -> Result<T, Disconnected> {
+        loop {
+            match self.try_recv() {
+                Ok(value) => return Ok(value),
+                Err(PopError::Disconnected) => return Err(Disconnected(())),
+                Err(PopError::Empty) => {}
+            }
+
+            self.shared.waiter.prepare(false);
+            // Orders the state store above before the checks below, as the
+            // sender's `wake` does the other way around.
+            fence(Ordering::SeqCst);
+            if self.ready() {
+                self.shared.waiter.cancel();
+                continue;
+            }
+            self.shared.waiter.sleep(None);
+        }
+    }
+
+    /// Number of lanes, that is, senders the receiver knows of.
+    pub fn lanes(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Whether `try_recv` could get further than last time.
+    fn ready(&mut self) -> bool {
+        self.shared.has_pending.load(Ordering::Relaxed)
+            || self
+                .lanes
+                .iter_mut()
+                .any(|lane| lane.peek().is_some() || lane.is_disconnected())
+    }
+
+    /// Picks up the lanes of new senders.
+    fn adopt(&mut self) {
+        if self.shared.has_pending.load(Ordering::Acquire) {
+            let mut pending = self.shared.pending.lock();
+            self.shared.has_pending.store(false, Ordering::Relaxed);
+            self.lanes.append(&mut pending);
+        }
+    }
+}
+
+impl<T: Send> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // Lanes not picked up yet are dropped too, so their senders see the
+        // receiver is gone.
+        let mut pending = self.shared.pending.lock();
+        self.shared.closed.store(true, Ordering::Relaxed);
+        pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let (mut a, mut receiver) = new::<String>(2);
+        let mut b = a.clone();
+        assert_eq!(receiver.try_recv(), Err(PopError::Empty));
+        assert_eq!(receiver.lanes(), 2);
+
+        // Each sender fills its own lane.
+        a.try_send("a1".to_string()).unwrap();
+        a.try_send("a2".to_string()).unwrap();
+        assert_eq!(
+            a.try_send("a3".to_string()),
+            Err(PushError::Full("a3".to_string()))
+        );
+        b.try_send("b1".to_string()).unwrap();
+
+        // Lanes take turns.
+        assert_eq!(receiver.try_recv().as_deref(), Ok("a1"));
+        assert_eq!(receiver.try_recv().as_deref(), Ok("b1"));
+        assert_eq!(receiver.try_recv().as_deref(), Ok("a2"));
+        assert_eq!(receiver.try_recv(), Err(PopError::Empty));
+
+        // A dropped sender's lane is drained, then removed.
+        b.try_send("b2".to_string()).unwrap();
+        drop(b);
+        assert_eq!(receiver.try_recv().as_deref(), Ok("b2"));
+        assert_eq!(receiver.try_recv(), Err(PopError::Empty));
+        assert_eq!(receiver.lanes(), 1);
+
+        drop(a);
+        assert_eq!(receiver.try_recv(), Err(PopError::Disconnected));
+        assert_eq!(receiver.recv(), Err(Disconnected(())));
+
+        // Senders find out when the receiver is gone, even with a new lane.
+        let (mut a, receiver) = new::<String>(2);
+        drop(receiver);
+        let mut b = a.clone();
+        assert_eq!(
+            a.try_send("x".to_string()),
+            Err(PushError::Disconnected("x".to_string()))
+        );
+        assert_eq!(b.send("y".to_string()), Err(Disconnected("y".to_string())));
+    }
+
+    #[test]
+    fn test_concurrent() {
+        const PER_SENDER: usize = 10_000;
+
+        let (sender, mut receiver) = new::<(usize, usize)>(16);
+        let t = thread::spawn(move || {
+            // Senders come and go while the receiver is waiting.
+            thread::sleep(Duration::from_millis(10));
+            thread::scope(|s| {
+                for p in 0..4 {
+                    let mut sender = sender.clone();
+                    s.spawn(move || {
+                        for i in 0..PER_SENDER {
+                            sender.send((p, i)).unwrap();
+                        }
+                    });
+                }
+                drop(sender);
+            });
+        });
+
+        // Each sender's values come in order, and `recv` only gives up once
+        // they're all gone.
+        let mut last = [None; 4];
+        let mut received = 0;
+        while let Ok((p, i)) = receiver.recv() {
+            assert!(last[p] < Some(i));
+            last[p] = Some(i);
+            received += 1;
+        }
+        assert_eq!(received, 4 * PER_SENDER);
+        t.join().unwrap();
+    }
+}
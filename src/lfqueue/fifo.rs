@@ -1,31 +1,63 @@
 use std::{
     cell::UnsafeCell,
-    mem::MaybeUninit,
+    mem::{ManuallyDrop, MaybeUninit},
     ops::Deref,
+    slice,
     sync::{
         Arc,
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering, fence},
     },
+    thread::{self, Thread},
+    time::{Duration, Instant},
 };
 
+use atomic_wait::{wait, wake_one};
+
+use crate::mutex::Mutex;
+
 // A struct to ensure cache line alignment to prevent **false sharing**.
 #[repr(align(64))]
-struct CachePadded<T>(pub T);
+pub(crate) struct CachePadded<T>(pub T);
 
 ///FIFO2:
 ///  - Use atomic operations to manage head and tail indices.
 ///  - Use UnsafeCell to solve interior mutability issues.
 ///  - Use `Ordering::SeqCst` to ensure strong memory ordering guarantees.
+///
 ///FIFO3:
 ///  - Use `CachePadded` struct to wrap atomic variables, preventing false sharing.
 ///  - Use `Ordering::Acquire` and `Ordering::Release` for better performance while maintaining correctness.
+///
 ///FIFO4:
 ///  - Use local cache variables in Producer and Consumer to reduce the number of atomic loads.
 ///  - Split `Fifo` into `Producer` and `Consumer` structs for better separation of concerns.
+///
 ///FIFO5:
 ///  - Introduce `Pusher` and `Popper` proxy objects to implement `zero copy`
 ///  - Use `Drop` trait to automatically handle head/tail updates when the proxy objects go out of scope.
 ///
+///FIFO6:
+///  - Batch operations (`reserve`/`commit`, `push_slice`, `pop_into`, `drain`) that move many slots
+///    but store `head`/`tail` only once per batch.
+///
+///FIFO7:
+///  - `Pusher` is a reservation: `write` commits it, dropping it without writing leaves the
+///    queue as it was.
+///  - `Popper::take` moves the value out, dropping the `Popper` still consumes it as in FIFO5.
+///    `Consumer::peek` looks at the oldest value without consuming it.
+///
+///FIFO8:
+///  - Blocking and timeout variants of push/pop that sleep only when the ring is full/empty.
+///  - A `Waiter` flag per side, so publishing `head`/`tail` only makes a syscall when the other
+///    side is actually asleep. The sides sleep on that flag rather than on `head`/`tail`
+///    themselves, as those are `AtomicU64` and `atomic_wait` only waits on 32-bit atomics.
+///  - Publishing only pays for the fence that pairs with a sleeper once the blocking API has been
+///    used, so `try_push`/`try_pop` alone cost what they did before.
+///
+///FIFO9:
+///  - Each side marks itself gone when dropped, so the other side gets a `Disconnected` error
+///    instead of waiting forever. Values left in the ring are dropped with `Shared`.
+///
 /// Guidelines for using atomic orderings
 /// 1. Use `Ordering::SeqCst` for simplicity and strong guarantees.
 /// 2. Use `Ordering::Acquire` for loads that other threads write to.
@@ -37,6 +69,94 @@ struct Shared<T: Send> {
     capacity: usize,
     head: CachePadded<AtomicU64>,
     tail: CachePadded<AtomicU64>,
+    // The consumer waits for `head` to move, the producer for `tail`.
+    consumer_waiter: CachePadded<Waiter>,
+    producer_waiter: CachePadded<Waiter>,
+    producer_alive: AtomicBool,
+    consumer_alive: AtomicBool,
+}
+
+// Waiter states.
+const IDLE: u32 = 0;
+/// Asleep in `atomic_wait::wait` on the state.
+const WAITING: u32 = 1;
+/// Asleep in `thread::park_timeout`, as `atomic_wait` can't time out.
+const PARKED: u32 = 2;
+
+/// How long the first sleep on a `Waiter` may last, see `prepare`.
+const FIRST_NAP: Duration = Duration::from_millis(1);
+
+/// Lets one side of the ring sleep until the other publishes its index.
+///
+/// The sleeper sets the state before checking the index one last time, and
+/// the other side checks the state after publishing, with SeqCst in between
+/// on both sides, so at least one of them sees the other.
+///
+/// The other side skips that fence until someone has slept here, so rings
+/// that are only ever polled don't pay for it.
+pub(crate) struct Waiter {
+    state: AtomicU32,
+    thread: Mutex<Option<Thread>>,
+    /// Set for good by the first `prepare`.
+    used: AtomicBool,
+}
+
+impl Waiter {
+    pub(crate) const fn new() -> Self {
+        Waiter {
+            state: AtomicU32::new(IDLE),
+            thread: Mutex::new(None),
+            used: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn prepare(&self, timed: bool) {
+        // The first sleeper can't tell whether the other side has seen `used`
+        // yet, and may miss a `wake` from a publish that hasn't. So it only
+        // naps, as if it had a deadline.
+        let first = !self.used.load(Ordering::Relaxed) && !self.used.swap(true, Ordering::SeqCst);
+        if timed || first {
+            *self.thread.lock() = Some(thread::current());
+            self.state.store(PARKED, Ordering::SeqCst);
+        } else {
+            self.state.store(WAITING, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn sleep(&self, deadline: Option<Instant>) {
+        match deadline {
+            Some(deadline) => thread::park_timeout(deadline - Instant::now()),
+            // Parked without a deadline, see `prepare`.
+            None if self.state.load(Ordering::Relaxed) == PARKED => thread::park_timeout(FIRST_NAP),
+            // Returns right away if `wake` got here first.
+            None => wait(&self.state, WAITING),
+        }
+        self.state.store(IDLE, Ordering::Relaxed);
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.state.store(IDLE, Ordering::Relaxed);
+    }
+
+    /// Called right after publishing an index.
+    pub(crate) fn wake(&self) {
+        if !self.used.load(Ordering::Relaxed) {
+            return;
+        }
+        fence(Ordering::SeqCst);
+        if self.state.load(Ordering::Relaxed) == IDLE {
+            return;
+        }
+        match self.state.swap(IDLE, Ordering::Relaxed) {
+            WAITING => wake_one(&self.state),
+            PARKED => {
+                if let Some(thread) = &*self.thread.lock() {
+                    thread.unpark();
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
 unsafe impl<T: Send> Send for Producer<T> {}
@@ -63,20 +183,11 @@ pub struct Pusher<'a, T: Send> {
 }
 
 impl<T: Send> Pusher<'_, T> {
+    /// Writes the slot and hands it to the consumer. Dropping the `Pusher`
+    /// without writing gives the slot back instead.
     pub fn write(self, value: T) {
         unsafe { (*self.slot).write(value) };
-        // self will be consumed here, then call the `drop` method
-    }
-}
-
-impl<T: Send> Drop for Pusher<'_, T> {
-    fn drop(&mut self) {
-        self.producer.head += 1;
-        self.producer
-            .shared
-            .head
-            .0
-            .store(self.producer.head, Ordering::Release);
+        self.producer.publish(1);
     }
 }
 
@@ -85,23 +196,32 @@ pub struct Popper<'a, T: Send> {
     slot: *const MaybeUninit<T>,
 }
 
+impl<T: Send> Popper<'_, T> {
+    /// Moves the value out and frees its slot for the producer. Dropping the
+    /// `Popper` without taking drops the value instead.
+    pub fn take(self) -> T {
+        let mut this = ManuallyDrop::new(self);
+        let value = unsafe { (*this.slot).assume_init_read() };
+        this.consumer.advance(1);
+        value
+    }
+
+    pub fn peek(&self) -> &T {
+        unsafe { (*self.slot).assume_init_ref() }
+    }
+}
+
 impl<'a, T: Send> Deref for Popper<'a, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
-        unsafe { (*self.slot).assume_init_ref() }
+        self.peek()
     }
 }
 
 impl<T: Send> Drop for Popper<'_, T> {
     fn drop(&mut self) {
         unsafe { (*self.slot.cast_mut()).assume_init_drop() };
-
-        self.consumer.tail += 1;
-        self.consumer
-            .shared
-            .tail
-            .0
-            .store(self.consumer.tail, Ordering::Release);
+        self.consumer.advance(1);
     }
 }
 
@@ -122,6 +242,10 @@ pub fn new<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
         capacity,
         head: CachePadded(AtomicU64::new(0)),
         tail: CachePadded(AtomicU64::new(0)),
+        consumer_waiter: CachePadded(Waiter::new()),
+        producer_waiter: CachePadded(Waiter::new()),
+        producer_alive: AtomicBool::new(true),
+        consumer_alive: AtomicBool::new(true),
     });
     let producer = Producer {
         shared: shared.clone(),
@@ -140,6 +264,50 @@ pub fn new<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
 #[derive(Debug, PartialEq, Eq)]
 pub struct FullError;
 
+/// The other side of the ring has been dropped. Carries the value that
+/// couldn't be pushed, if any.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Disconnected<T = ()>(pub T);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PopError {
+    Empty,
+    /// Empty, and the producer has been dropped.
+    Disconnected,
+}
+
+impl<T: Send> Shared<T> {
+    fn slot(&self, pos: u64) -> *mut MaybeUninit<T> {
+        let index = (pos % self.capacity as u64) as usize;
+        unsafe { self.buffer.get_unchecked(index).get() }
+    }
+
+    /// The `len` slots starting at `pos`, split in two where the ring wraps.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own those slots, and `len` must not exceed the capacity.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn slots(&self, pos: u64, len: usize) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let start = (pos % self.capacity as u64) as usize;
+        let first = len.min(self.capacity - start);
+        // `UnsafeCell` is `repr(transparent)`, so the buffer is a plain array.
+        let base = UnsafeCell::raw_get(self.buffer.as_ptr());
+        unsafe {
+            (
+                slice::from_raw_parts_mut(base.add(start), first),
+                slice::from_raw_parts_mut(base, len - first),
+            )
+        }
+    }
+}
+
 impl<T: Send> Producer<T> {
     pub fn push(&mut self) -> Result<Pusher<'_, T>, FullError> {
         if self.head - self.cache_tail == self.shared.capacity as u64 {
@@ -159,6 +327,117 @@ impl<T: Send> Producer<T> {
             slot,
         })
     }
+
+    /// Number of free slots, looking at the consumer's tail only if the
+    /// cached one leaves fewer than `wanted`.
+    fn free(&mut self, wanted: usize) -> usize {
+        let capacity = self.shared.capacity as u64;
+        if capacity - (self.head - self.cache_tail) < wanted as u64 {
+            self.cache_tail = self.shared.tail.0.load(Ordering::Acquire);
+        }
+        (capacity - (self.head - self.cache_tail)) as usize
+    }
+
+    /// Up to `n` free slots, split in two where the ring wraps. Write to a
+    /// prefix of them and `commit` it to hand it to the consumer.
+    pub fn reserve(&mut self, n: usize) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let n = n.min(self.free(n));
+        unsafe { self.shared.slots(self.head, n) }
+    }
+
+    /// Publishes the first `n` slots of the last `reserve`.
+    ///
+    /// # Safety
+    ///
+    /// Those slots must have been initialized.
+    pub unsafe fn commit(&mut self, n: usize) {
+        debug_assert!(n <= self.free(n));
+        self.publish(n);
+    }
+
+    fn publish(&mut self, n: usize) {
+        self.head += n as u64;
+        self.shared.head.0.store(self.head, Ordering::Release);
+        self.shared.consumer_waiter.0.wake();
+    }
+
+    /// Whether the consumer has been dropped. `push` doesn't check this.
+    pub fn is_disconnected(&self) -> bool {
+        !self.shared.consumer_alive.load(Ordering::Relaxed)
+    }
+
+    pub fn try_push(&mut self, value: T) -> Result<(), PushError<T>> {
+        if self.is_disconnected() {
+            return Err(PushError::Disconnected(value));
+        }
+        match self.push() {
+            Ok(pusher) => {
+                pusher.write(value);
+                Ok(())
+            }
+            Err(FullError) => Err(PushError::Full(value)),
+        }
+    }
+
+    /// Pushes `value`, sleeping while the ring is full.
+    pub fn push_blocking(&mut self, value: T) -> Result<(), Disconnected<T>> {
+        self.push_deadline(value, None).map_err(|e| match e {
+            PushError::Disconnected(value) => Disconnected(value),
+            PushError::Full(_) => unreachable!(),
+        })
+    }
+
+    /// Pushes `value`, or hands it back as `Full` if the ring stays full
+    /// for `timeout`.
+    pub fn push_timeout(&mut self, value: T, timeout: Duration) -> Result<(), PushError<T>> {
+        self.push_deadline(value, Some(Instant::now() + timeout))
+    }
+
+    fn push_deadline(&mut self, value: T, deadline: Option<Instant>) -> Result<(), PushError<T>> {
+        let capacity = self.shared.capacity as u64;
+        let mut value = value;
+        loop {
+            match self.try_push(value) {
+                Err(PushError::Full(v)) => value = v,
+                result => return result,
+            }
+
+            let waiter = &self.shared.producer_waiter.0;
+            waiter.prepare(deadline.is_some());
+            if self.head - self.shared.tail.0.load(Ordering::SeqCst) < capacity
+                || !self.shared.consumer_alive.load(Ordering::SeqCst)
+            {
+                waiter.cancel();
+                continue;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                waiter.cancel();
+                return Err(PushError::Full(value));
+            }
+            waiter.sleep(deadline);
+        }
+    }
+
+    /// Pushes as many values from the iterator as fit, and returns how many
+    /// that was. Pass `&mut iter` to keep the rest.
+    pub fn extend_from_iter<I: IntoIterator<Item = T>>(&mut self, iter: I) -> usize {
+        let (first, second) = self.reserve(usize::MAX);
+        let mut count = 0;
+        for (slot, value) in first.iter_mut().chain(second).zip(iter) {
+            slot.write(value);
+            count += 1;
+        }
+        unsafe { self.commit(count) };
+        count
+    }
+
+    /// Pushes clones of as many values as fit, and returns how many that was.
+    pub fn push_slice(&mut self, values: &[T]) -> usize
+    where
+        T: Clone,
+    {
+        self.extend_from_iter(values.iter().cloned())
+    }
 }
 
 impl<T: Send> Consumer<T> {
@@ -180,10 +459,384 @@ impl<T: Send> Consumer<T> {
             slot,
         })
     }
+
+    /// The oldest value, left in the queue.
+    pub fn peek(&mut self) -> Option<&T> {
+        if self.available(1) == 0 {
+            return None;
+        }
+        Some(unsafe { (*self.shared.slot(self.tail)).assume_init_ref() })
+    }
+
+    /// Number of values ready, looking at the producer's head only if the
+    /// cached one leaves fewer than `wanted`.
+    fn available(&mut self, wanted: usize) -> usize {
+        if self.cache_head - self.tail < wanted as u64 {
+            self.cache_head = self.shared.head.0.load(Ordering::Acquire);
+        }
+        (self.cache_head - self.tail) as usize
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.tail += n as u64;
+        self.shared.tail.0.store(self.tail, Ordering::Release);
+        self.shared.producer_waiter.0.wake();
+    }
+
+    /// Whether the producer has been dropped. There may still be values
+    /// left to pop.
+    pub fn is_disconnected(&self) -> bool {
+        !self.shared.producer_alive.load(Ordering::Relaxed)
+    }
+
+    pub fn try_pop(&mut self) -> Result<T, PopError> {
+        if let Some(popper) = self.pop() {
+            return Ok(popper.take());
+        }
+        // Acquire to see everything the producer pushed before it went away.
+        if self.shared.producer_alive.load(Ordering::Acquire) {
+            return Err(PopError::Empty);
+        }
+        match self.pop() {
+            Some(popper) => Ok(popper.take()),
+            None => Err(PopError::Disconnected),
+        }
+    }
+
+    /// Pops a value, sleeping while the ring is empty.
+    pub fn pop_blocking(&mut self) -> Result<T, Disconnected> {
+        self.pop_deadline(None).map_err(|e| match e {
+            PopError::Disconnected => Disconnected(()),
+            PopError::Empty => unreachable!(),
+        })
+    }
+
+    /// Pops a value, or gives up with `Empty` if the ring stays empty for
+    /// `timeout`.
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Result<T, PopError> {
+        self.pop_deadline(Some(Instant::now() + timeout))
+    }
+
+    fn pop_deadline(&mut self, deadline: Option<Instant>) -> Result<T, PopError> {
+        loop {
+            match self.try_pop() {
+                Err(PopError::Empty) => {}
+                result => return result,
+            }
+
+            let waiter = &self.shared.consumer_waiter.0;
+            waiter.prepare(deadline.is_some());
+            if self.shared.head.0.load(Ordering::SeqCst) != self.tail
+                || !self.shared.producer_alive.load(Ordering::SeqCst)
+            {
+                waiter.cancel();
+                continue;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                waiter.cancel();
+                return Err(PopError::Empty);
+            }
+            waiter.sleep(deadline);
+        }
+    }
+
+    /// Moves every value that's ready into `out`, and returns how many that was.
+    pub fn pop_into(&mut self, out: &mut Vec<T>) -> usize {
+        let n = self.available(usize::MAX);
+        let (first, second) = unsafe { self.shared.slots(self.tail, n) };
+        out.reserve(n);
+        for slot in first.iter().chain(&*second) {
+            out.push(unsafe { slot.assume_init_read() });
+        }
+        self.advance(n);
+        n
+    }
+
+    /// Pops up to `n` values through an iterator. Values it doesn't get to
+    /// are dropped with it, like with `Vec::drain`.
+    pub fn drain(&mut self, n: usize) -> Drain<'_, T> {
+        let len = n.min(self.available(n));
+        let start = self.tail;
+        // Moved past the values up front, like `Vec::drain` does, so that
+        // forgetting the `Drain` leaks them instead of handing them out
+        // again. The producer only sees it once the `Drain` is dropped.
+        self.tail += len as u64;
+        Drain {
+            consumer: self,
+            start,
+            len,
+            taken: 0,
+        }
+    }
+}
+
+pub struct Drain<'a, T: Send> {
+    consumer: &'a mut Consumer<T>,
+    start: u64,
+    len: usize,
+    taken: usize,
+}
+
+impl<T: Send> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.taken == self.len {
+            return None;
+        }
+        let slot = self.consumer.shared.slot(self.start + self.taken as u64);
+        self.taken += 1;
+        Some(unsafe { (*slot).assume_init_read() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.taken;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: Send> ExactSizeIterator for Drain<'_, T> {}
+
+impl<T: Send> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        for pos in self.taken..self.len {
+            let slot = self.consumer.shared.slot(self.start + pos as u64);
+            unsafe { (*slot).assume_init_drop() };
+        }
+        // Publishes the `tail` that `drain` moved.
+        self.consumer.advance(0);
+    }
+}
+
+impl<T: Send> Drop for Producer<T> {
+    fn drop(&mut self) {
+        // Release for `try_pop`, and it also has to come before `wake` checks
+        // for a sleeping consumer.
+        self.shared.producer_alive.store(false, Ordering::SeqCst);
+        self.shared.consumer_waiter.0.wake();
+    }
 }
 
 impl<T: Send> Drop for Consumer<T> {
     fn drop(&mut self) {
-        while self.pop().is_some() {}
+        // A forgotten `Drain` leaves `tail` unpublished, and `Shared::drop`
+        // mustn't drop the values it handed out.
+        self.shared.tail.0.store(self.tail, Ordering::Release);
+        self.shared.consumer_alive.store(false, Ordering::SeqCst);
+        self.shared.producer_waiter.0.wake();
+    }
+}
+
+impl<T: Send> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // Both sides are gone, so whatever is between tail and head is ours.
+        let (tail, head) = (*self.tail.0.get_mut(), *self.head.0.get_mut());
+        for pos in tail..head {
+            unsafe { (*self.slot(pos)).assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_reservation() {
+        let (mut producer, mut consumer) = new::<String>(1);
+
+        // Dropping a `Pusher` aborts: nothing is published, the slot stays free.
+        let _ = producer.push().unwrap();
+        assert!(consumer.pop().is_none());
+
+        producer.push().unwrap().write("a".to_string());
+        assert_eq!(producer.push().err(), Some(FullError));
+
+        // Peeking leaves the value in the queue.
+        assert_eq!(consumer.peek().map(String::as_str), Some("a"));
+        assert_eq!(producer.push().err(), Some(FullError));
+        let popper = consumer.pop().unwrap();
+        assert_eq!(popper.peek(), "a");
+        assert_eq!(popper.take(), "a");
+        assert!(consumer.peek().is_none());
+        assert!(consumer.pop().is_none());
+
+        // Dropping a `Popper` consumes the value.
+        producer.push().unwrap().write("b".to_string());
+        drop(consumer.pop());
+        assert!(consumer.pop().is_none());
+        producer.push().unwrap().write("c".to_string());
+        // "c" is dropped with the consumer.
+    }
+
+    #[test]
+    fn test_batch() {
+        let (mut producer, mut consumer) = new::<String>(4);
+
+        // Wrap the ring around first, so batches are split in two.
+        producer.push().unwrap().write("x".to_string());
+        producer.push().unwrap().write("y".to_string());
+        assert_eq!(consumer.drain(2).collect::<Vec<_>>(), ["x", "y"]);
+
+        let values: Vec<_> = (0..6).map(|i| i.to_string()).collect();
+        assert_eq!(producer.push_slice(&values), 4);
+        assert_eq!(producer.push_slice(&values), 0);
+
+        let mut out = Vec::new();
+        assert_eq!(consumer.pop_into(&mut out), 4);
+        assert_eq!(out, values[..4]);
+
+        let (first, second) = producer.reserve(3);
+        assert_eq!((first.len(), second.len()), (2, 1));
+        first[0].write("a".to_string());
+        first[1].write("b".to_string());
+        unsafe { producer.commit(2) };
+
+        let mut iter = values.into_iter();
+        assert_eq!(producer.extend_from_iter(&mut iter), 2);
+        assert_eq!(iter.next().as_deref(), Some("2"));
+
+        // Takes one, drops one, leaves the rest.
+        assert_eq!(consumer.drain(2).next().as_deref(), Some("a"));
+        assert_eq!(consumer.drain(5).collect::<Vec<_>>(), ["0", "1"]);
+        assert!(consumer.pop().is_none());
+    }
+
+    #[test]
+    fn test_drain_forget() {
+        static NUM_DROPS: AtomicU32 = AtomicU32::new(0);
+
+        #[derive(Debug)]
+        struct DetectDrop(u32);
+
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let (mut producer, mut consumer) = new::<DetectDrop>(4);
+        for i in 0..4 {
+            producer.try_push(DetectDrop(i)).unwrap();
+        }
+
+        // Forgetting a `Drain` leaks what it didn't hand out, and never hands
+        // anything out twice.
+        let mut drain = consumer.drain(2);
+        assert_eq!(drain.next().map(|value| value.0), Some(0));
+        std::mem::forget(drain);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        assert_eq!(consumer.try_pop().map(|value| value.0), Ok(2));
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+
+        // The leaked slot is free again.
+        producer.try_push(DetectDrop(4)).unwrap();
+        producer.try_push(DetectDrop(5)).unwrap();
+        producer.try_push(DetectDrop(6)).unwrap();
+        assert!(producer.try_push(DetectDrop(7)).is_err());
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+
+        // 3, 4, 5 and 6 are dropped with the ring, 1 never is.
+        drop((producer, consumer));
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 7);
+    }
+
+    #[test]
+    fn test_blocking() {
+        const N: u64 = 10_000;
+        let (mut producer, mut consumer) = new::<u64>(2);
+
+        // Polling alone leaves the waiters unused, so publishing skips the fence.
+        let shared = consumer.shared.clone();
+        producer.try_push(0).unwrap();
+        assert_eq!(consumer.try_pop(), Ok(0));
+        assert!(!shared.consumer_waiter.0.used.load(Ordering::Relaxed));
+
+        assert_eq!(
+            consumer.pop_timeout(Duration::from_millis(10)),
+            Err(PopError::Empty)
+        );
+        assert!(shared.consumer_waiter.0.used.load(Ordering::Relaxed));
+
+        let t = thread::spawn(move || {
+            for i in 0..N {
+                producer.push_blocking(i).unwrap();
+            }
+            producer
+        });
+        for i in 0..N {
+            assert_eq!(consumer.pop_blocking(), Ok(i));
+        }
+        let mut producer = t.join().unwrap();
+
+        producer.push_blocking(0).unwrap();
+        producer.push_blocking(1).unwrap();
+        assert_eq!(
+            producer.push_timeout(2, Duration::from_millis(10)),
+            Err(PushError::Full(2))
+        );
+
+        // A timed wait is woken up early too.
+        let t = thread::spawn(move || producer.push_timeout(2, Duration::from_secs(60)));
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(consumer.pop_blocking(), Ok(0));
+        assert_eq!(t.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (mut producer, mut consumer) = new::<String>(2);
+        producer.try_push("a".to_string()).unwrap();
+        assert_eq!(consumer.try_pop(), Ok("a".to_string()));
+        assert_eq!(consumer.try_pop(), Err(PopError::Empty));
+
+        // Values pushed before the producer went away can still be popped.
+        producer.try_push("b".to_string()).unwrap();
+        drop(producer);
+        assert!(consumer.is_disconnected());
+        assert_eq!(consumer.try_pop(), Ok("b".to_string()));
+        assert_eq!(consumer.try_pop(), Err(PopError::Disconnected));
+        assert_eq!(consumer.pop_blocking(), Err(Disconnected(())));
+
+        // A sleeping consumer is woken up.
+        let (producer, mut consumer) = new::<String>(2);
+        let t = thread::spawn(move || consumer.pop_blocking());
+        thread::sleep(Duration::from_millis(10));
+        drop(producer);
+        assert_eq!(t.join().unwrap(), Err(Disconnected(())));
+
+        // A sleeping producer is woken up and gets its value back.
+        let (mut producer, consumer) = new::<String>(1);
+        producer.try_push("c".to_string()).unwrap();
+        let t = thread::spawn(move || producer.push_blocking("d".to_string()));
+        thread::sleep(Duration::from_millis(10));
+        // "c" is dropped with the ring.
+        drop(consumer);
+        assert_eq!(t.join().unwrap(), Err(Disconnected("d".to_string())));
+    }
+
+    #[test]
+    fn test_batch_concurrent() {
+        const N: u64 = 100_000;
+        let (mut producer, mut consumer) = new::<u64>(64);
+
+        let t = thread::spawn(move || {
+            let mut values = 0..N;
+            while !values.is_empty() {
+                if producer.extend_from_iter(&mut values) == 0 {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut out = Vec::new();
+        while (out.len() as u64) < N {
+            if consumer.pop_into(&mut out) == 0 {
+                thread::yield_now();
+            }
+        }
+        t.join().unwrap();
+        assert!(out.into_iter().eq(0..N));
     }
 }
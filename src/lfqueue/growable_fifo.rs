@@ -0,0 +1,250 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr,
+    sync::{
+        Arc,
+        atomic::{AtomicPtr, AtomicU64, Ordering},
+    },
+};
+
+use super::fifo::CachePadded;
+
+/// An SPSC ring like `fifo`'s, except a push never fails: when the ring is
+/// full, the producer allocates one twice as large and carries on there.
+///
+///  - Each ring has its own `head`/`tail`, cached on both sides as in `fifo`.
+///  - The producer links the new ring from the full one's `next` after its
+///    last push there, so once the consumer sees `next`, the old ring's
+///    `head` is final. It drains the old ring, then frees it and moves on.
+///  - Whatever the consumer hasn't got to yet is dropped with `Shared`, once
+///    both sides are gone.
+struct Ring<T> {
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // The capacity is a power of two.
+    mask: u64,
+    head: CachePadded<AtomicU64>,
+    tail: CachePadded<AtomicU64>,
+    next: AtomicPtr<Ring<T>>,
+}
+
+impl<T> Ring<T> {
+    fn new(capacity: usize) -> *mut Self {
+        let buffer = (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect();
+        Box::into_raw(Box::new(Ring {
+            buffer,
+            mask: capacity as u64 - 1,
+            head: CachePadded(AtomicU64::new(0)),
+            tail: CachePadded(AtomicU64::new(0)),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+
+    fn capacity(&self) -> u64 {
+        self.mask + 1
+    }
+
+    fn slot(&self, pos: u64) -> *mut MaybeUninit<T> {
+        unsafe { self.buffer.get_unchecked((pos & self.mask) as usize).get() }
+    }
+}
+
+struct Shared<T> {
+    /// The ring the consumer is on, where the chain starts.
+    first: AtomicPtr<Ring<T>>,
+}
+
+pub struct Producer<T: Send> {
+    shared: Arc<Shared<T>>,
+    ring: *mut Ring<T>,
+    head: u64,
+    // Local cache of consumer's tail to reduce atomic loads
+    cache_tail: u64,
+}
+
+pub struct Consumer<T: Send> {
+    shared: Arc<Shared<T>>,
+    ring: *mut Ring<T>,
+    tail: u64,
+    // Local cache of producer's head to reduce atomic loads
+    cache_head: u64,
+}
+
+unsafe impl<T: Send> Send for Producer<T> {}
+unsafe impl<T: Send> Send for Consumer<T> {}
+
+/// Starts with room for `capacity` values, rounded up to a power of two.
+pub fn new<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0);
+
+    let ring = Ring::new(capacity.next_power_of_two());
+    let shared = Arc::new(Shared {
+        first: AtomicPtr::new(ring),
+    });
+    let producer = Producer {
+        shared: shared.clone(),
+        ring,
+        head: 0,
+        cache_tail: 0,
+    };
+    let consumer = Consumer {
+        shared,
+        ring,
+        tail: 0,
+        cache_head: 0,
+    };
+
+    (producer, consumer)
+}
+
+impl<T: Send> Producer<T> {
+    pub fn push(&mut self, value: T) {
+        let ring = unsafe { &*self.ring };
+        if self.head - self.cache_tail == ring.capacity() {
+            // Update local cache of tail
+            self.cache_tail = ring.tail.0.load(Ordering::Acquire);
+
+            if self.head - self.cache_tail == ring.capacity() {
+                self.grow(value);
+                return;
+            }
+        }
+
+        unsafe { (*ring.slot(self.head)).write(value) };
+        self.head += 1;
+        ring.head.0.store(self.head, Ordering::Release);
+    }
+
+    /// Moves on to a ring twice the size, starting with `value`.
+    fn grow(&mut self, value: T) {
+        let old = unsafe { &*self.ring };
+        let new = Ring::new(old.capacity() as usize * 2);
+        unsafe {
+            (*(*new).slot(0)).write(value);
+            *(*new).head.0.get_mut() = 1;
+        }
+        // Release to publish the new ring, and our last `head` in the old one.
+        old.next.store(new, Ordering::Release);
+        // The old ring is the consumer's to free now.
+        self.ring = new;
+        self.head = 1;
+        self.cache_tail = 0;
+    }
+
+    /// Capacity of the ring the producer is writing to.
+    pub fn capacity(&self) -> usize {
+        unsafe { (*self.ring).capacity() as usize }
+    }
+}
+
+impl<T: Send> Consumer<T> {
+    pub fn pop(&mut self) -> Option<T> {
+        loop {
+            let ring = unsafe { &*self.ring };
+            if self.tail == self.cache_head {
+                // Update local cache of head
+                self.cache_head = ring.head.0.load(Ordering::Acquire);
+
+                if self.tail == self.cache_head {
+                    let next = ring.next.load(Ordering::Acquire);
+                    if next.is_null() {
+                        return None;
+                    }
+                    // The producer may have filled the ring up before moving on.
+                    self.cache_head = ring.head.0.load(Ordering::Acquire);
+                    if self.tail == self.cache_head {
+                        self.switch(next);
+                        continue;
+                    }
+                }
+            }
+
+            let value = unsafe { (*ring.slot(self.tail)).assume_init_read() };
+            self.tail += 1;
+            ring.tail.0.store(self.tail, Ordering::Release);
+            return Some(value);
+        }
+    }
+
+    /// Frees the drained ring and moves on to `next`.
+    fn switch(&mut self, next: *mut Ring<T>) {
+        self.shared.first.store(next, Ordering::Relaxed);
+        // The producer left it for good when it linked `next`.
+        unsafe { drop(Box::from_raw(self.ring)) };
+        self.ring = next;
+        self.tail = 0;
+        self.cache_head = 0;
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // Both sides are gone, so whatever is between tail and head in each
+        // ring is ours.
+        let mut ring = *self.first.get_mut();
+        while !ring.is_null() {
+            let mut boxed = unsafe { Box::from_raw(ring) };
+            let (tail, head) = (*boxed.tail.0.get_mut(), *boxed.head.0.get_mut());
+            for pos in tail..head {
+                unsafe { (*boxed.slot(pos)).assume_init_drop() };
+            }
+            ring = *boxed.next.get_mut();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        let (mut producer, mut consumer) = new::<String>(3);
+        assert_eq!(producer.capacity(), 4);
+        assert_eq!(consumer.pop(), None);
+
+        for i in 0..10 {
+            producer.push(i.to_string());
+        }
+        assert_eq!(producer.capacity(), 8);
+        for i in 0..6 {
+            assert_eq!(consumer.pop(), Some(i.to_string()));
+        }
+        // Grow again while the consumer is still on an older ring.
+        for i in 10..30 {
+            producer.push(i.to_string());
+        }
+        assert_eq!(producer.capacity(), 16);
+        for i in 6..12 {
+            assert_eq!(consumer.pop(), Some(i.to_string()));
+        }
+        // The rest, across two rings, is dropped with the queue.
+    }
+
+    #[test]
+    fn test_concurrent() {
+        const N: u64 = 100_000;
+
+        let (mut producer, mut consumer) = new::<u64>(1);
+        let t = thread::spawn(move || {
+            for i in 0..N {
+                producer.push(i);
+            }
+            producer.capacity()
+        });
+        for i in 0..N {
+            loop {
+                if let Some(value) = consumer.pop() {
+                    assert_eq!(value, i);
+                    break;
+                }
+                thread::yield_now();
+            }
+        }
+        assert!(t.join().unwrap().is_power_of_two());
+        assert_eq!(consumer.pop(), None);
+    }
+}
@@ -1,42 +1,365 @@
 use std::{
+    hint,
+    marker::PhantomData,
+    mem::MaybeUninit,
     ptr,
-    sync::atomic::{AtomicPtr, Ordering},
+    sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
 };
 
+use crate::{arc::Arc, epoch, hazard::HazardPointer};
+
+/// How a `LockFreeQueue` keeps the nodes it reads alive while another
+/// thread may be popping them.
+pub trait Reclaim {
+    /// Held for the duration of a single `push` or `pop`.
+    type Guard;
+
+    fn pin() -> Self::Guard;
+
+    /// Calls `load` until the pointer it returns is kept alive, for as long
+    /// as `guard` lives and `slot` (0 or 1) isn't reused.
+    fn protect_with<T>(
+        guard: &mut Self::Guard,
+        slot: usize,
+        load: impl FnMut() -> *mut T,
+    ) -> *mut T;
+
+    /// Loads `src`, keeping the node it points to alive for as long as
+    /// `guard` lives and `slot` (0 or 1) isn't reused.
+    fn protect<T>(guard: &mut Self::Guard, slot: usize, src: &AtomicPtr<T>) -> *mut T {
+        Self::protect_with(guard, slot, || src.load(Ordering::Acquire))
+    }
+
+    /// Calls `free(ptr)` once no guard can reach `ptr` anymore.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must already be unlinked, and `free(ptr)` must be fine to run
+    /// on any thread, at any later time.
+    unsafe fn retire_with(guard: &Self::Guard, ptr: *mut u8, free: unsafe fn(*mut u8));
+
+    /// Frees `ptr` as a `Box<T>` once no guard can reach it anymore.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Box::into_raw` and must already be unlinked.
+    unsafe fn retire<T>(guard: &Self::Guard, ptr: *mut T) {
+        unsafe fn drop_box<T>(ptr: *mut u8) {
+            unsafe { drop(Box::from_raw(ptr.cast::<T>())) }
+        }
+        unsafe { Self::retire_with(guard, ptr.cast(), drop_box::<T>) }
+    }
+}
+
+/// Reclaims nodes with `crate::epoch`. The default.
+pub struct Epoch;
+
+impl Reclaim for Epoch {
+    type Guard = epoch::Guard;
+
+    fn pin() -> Self::Guard {
+        epoch::pin()
+    }
+
+    fn protect_with<T>(
+        _guard: &mut Self::Guard,
+        _slot: usize,
+        mut load: impl FnMut() -> *mut T,
+    ) -> *mut T {
+        load()
+    }
+
+    unsafe fn retire_with(guard: &Self::Guard, ptr: *mut u8, free: unsafe fn(*mut u8)) {
+        unsafe { guard.defer_unchecked(ptr, free) }
+    }
+}
+
+/// Reclaims nodes with `crate::hazard`, which bounds the garbage a stalled
+/// thread can hold on to, at the cost of a fence per protected load.
+pub struct Hazard;
+
+impl Reclaim for Hazard {
+    type Guard = [HazardPointer; 2];
+
+    fn pin() -> Self::Guard {
+        [HazardPointer::new(), HazardPointer::new()]
+    }
+
+    fn protect_with<T>(
+        guard: &mut Self::Guard,
+        slot: usize,
+        load: impl FnMut() -> *mut T,
+    ) -> *mut T {
+        guard[slot].protect_with(load)
+    }
+
+    unsafe fn retire_with(_guard: &Self::Guard, ptr: *mut u8, free: unsafe fn(*mut u8)) {
+        unsafe { crate::hazard::retire_with(ptr, free) }
+    }
+}
+
 struct Node<T> {
-    data: Option<T>,
+    // Uninitialized in the sentinel, and moved out when a node becomes it.
+    data: MaybeUninit<T>,
     next: AtomicPtr<Node<T>>,
+    /// The pool a retired node goes back to, holding a reference to it.
+    /// Only set between `retire_with` and `recycle`.
+    pool: AtomicPtr<NodePool<T>>,
+    /// Number of `peek`s reading `data`. The pop that takes it waits for them.
+    readers: AtomicUsize,
+}
+
+impl<T> Node<T> {
+    fn new(data: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            data,
+            next: AtomicPtr::new(ptr::null_mut()),
+            pool: AtomicPtr::new(ptr::null_mut()),
+            readers: AtomicUsize::new(0),
+        }))
+    }
+}
+
+/// Bits of a `NodePool::top` that hold the address, the rest is the tag.
+const ADDR_BITS: u32 = if usize::BITS == 32 { 32 } else { 48 };
+
+/// A Treiber stack of empty nodes, linked through their `next` pointers.
+///
+/// Nodes only get here once the queue's reclaimer is done with them. A popper
+/// may still read the `next` of a node that was popped and reused in the
+/// meantime, so the top pointer carries a tag that changes on every push and
+/// pop, which makes its CAS fail in that case (the ABA problem). That read
+/// also needs the node to still be allocated, so a popper protects it with
+/// the queue's reclaimer, and nodes that have been in the pool are only ever
+/// freed through the reclaimer, or when nobody can be popping.
+struct NodePool<T> {
+    top: AtomicU64,
+    len: AtomicUsize,
+    cap: usize,
+    _marker: PhantomData<Node<T>>,
+}
+
+// Only ever holds empty nodes.
+unsafe impl<T> Send for NodePool<T> {}
+unsafe impl<T> Sync for NodePool<T> {}
+
+impl<T> NodePool<T> {
+    fn new(cap: usize) -> Self {
+        NodePool {
+            top: AtomicU64::new(pack(ptr::null_mut::<Node<T>>(), 0)),
+            len: AtomicUsize::new(0),
+            cap,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Retires the node instead if the pool is full.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be empty and no longer reachable through the queue.
+    unsafe fn push<R: Reclaim>(&self, node: *mut Node<T>) {
+        if self.len.fetch_add(1, Ordering::Relaxed) >= self.cap {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            // A popper may still be looking at it, if it has been here before.
+            unsafe { R::retire(&R::pin(), node) };
+            return;
+        }
+        let node_ref = unsafe { &*node };
+        let mut top = self.top.load(Ordering::Relaxed);
+        loop {
+            let (next, tag) = unpack::<Node<T>>(top);
+            node_ref.next.store(next, Ordering::Relaxed);
+            // Release to publish the `next` we just wrote.
+            match self.top.compare_exchange_weak(
+                top,
+                pack(node, tag.wrapping_add(1)),
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(current) => top = current,
+            }
+        }
+    }
+
+    fn pop<R: Reclaim>(&self, guard: &mut R::Guard) -> Option<*mut Node<T>> {
+        loop {
+            let node = R::protect_with(guard, 1, || {
+                unpack::<Node<T>>(self.top.load(Ordering::Acquire)).0
+            });
+            if node.is_null() {
+                return None;
+            }
+            let top = self.top.load(Ordering::Acquire);
+            let (current, tag) = unpack::<Node<T>>(top);
+            if current != node {
+                continue;
+            }
+            // Fine to read even if someone else takes the node first, as
+            // it's protected. The tag then makes the CAS fail.
+            let next = unsafe { &*node }.next.load(Ordering::Relaxed);
+            if self
+                .top
+                .compare_exchange(
+                    top,
+                    pack(next, tag.wrapping_add(1)),
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                return Some(node);
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Frees every pooled node.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may be popping.
+    unsafe fn clear(&self) {
+        // Takes the whole stack, keeping the tag.
+        let top = self
+            .top
+            .fetch_and(!((1 << ADDR_BITS) - 1), Ordering::Acquire);
+        let mut node = unpack::<Node<T>>(top).0;
+        while !node.is_null() {
+            let next = unsafe { &*node }.next.load(Ordering::Relaxed);
+            unsafe { drop(Box::from_raw(node)) };
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            node = next;
+        }
+    }
 }
 
-pub struct LockFreeQueue<T> {
+impl<T> Drop for NodePool<T> {
+    fn drop(&mut self) {
+        unsafe { self.clear() };
+    }
+}
+
+fn pack<N>(ptr: *mut N, tag: u64) -> u64 {
+    let addr = ptr.expose_provenance() as u64;
+    // Not just a debug check: with pointer tagging (aarch64 TBI/MTE) or
+    // 5-level paging (x86 LA57), the tag would silently clobber the address.
+    assert!(
+        addr >> ADDR_BITS == 0,
+        "node address {addr:#x} doesn't fit in {ADDR_BITS} bits"
+    );
+    addr | tag << ADDR_BITS
+}
+
+fn unpack<N>(packed: u64) -> (*mut N, u64) {
+    let addr = packed & ((1 << ADDR_BITS) - 1);
+    (
+        ptr::with_exposed_provenance_mut(addr as usize),
+        packed >> ADDR_BITS,
+    )
+}
+
+/// Hands a retired node back to its pool, see `LockFreeQueue::pop`.
+unsafe fn recycle<T, R: Reclaim>(node: *mut u8) {
+    let node = node.cast::<Node<T>>();
+    let pool = unsafe { &*node }
+        .pool
+        .swap(ptr::null_mut(), Ordering::Relaxed);
+    let pool = unsafe { Arc::<NodePool<T>>::from_raw(pool) };
+    unsafe { pool.push::<R>(node) };
+}
+
+/// A Michael–Scott queue: an unbounded MPMC queue that can be shared between
+/// threads through `&self`. Popped nodes are freed through `R`, as other
+/// threads may still be reading them.
+///
+/// A queue made with `with_pool` keeps up to `cap` freed nodes around and
+/// reuses them for pushes, instead of going to the allocator every time.
+/// See `BoundedQueue` for one with a capacity limit.
+pub struct LockFreeQueue<T, R: Reclaim = Epoch> {
     head: AtomicPtr<Node<T>>,
     tail: AtomicPtr<Node<T>>,
+    // In an `Arc`, as retired nodes may come back after the queue is gone.
+    pool: Option<Arc<NodePool<T>>>,
+    _marker: PhantomData<(T, R)>,
 }
 
-impl<T> LockFreeQueue<T> {
+// Values are pushed on one thread and popped on another, so `T: Send` is all
+// `push` and `pop` need. `peek` clones through a shared reference, possibly
+// on several threads at once, so it asks for `T: Sync` on top.
+unsafe impl<T: Send, R: Reclaim> Send for LockFreeQueue<T, R> {}
+unsafe impl<T: Send, R: Reclaim> Sync for LockFreeQueue<T, R> {}
+
+impl<T, R: Reclaim> LockFreeQueue<T, R> {
     pub fn new() -> Self {
-        let sentinel = Box::new(Node {
-            data: None,
-            next: AtomicPtr::new(std::ptr::null_mut()),
-        });
-        let sentinel_ptr = Box::into_raw(sentinel);
+        Self::with_config(None)
+    }
+
+    /// A queue that recycles up to `cap` nodes.
+    pub fn with_pool(cap: usize) -> Self {
+        Self::with_config(Some(Arc::new(NodePool::new(cap))))
+    }
+
+    fn with_config(pool: Option<Arc<NodePool<T>>>) -> Self {
+        let sentinel_ptr = Node::new(MaybeUninit::uninit());
 
         let head = AtomicPtr::new(sentinel_ptr);
         let tail = AtomicPtr::new(sentinel_ptr);
 
-        LockFreeQueue { head, tail }
+        LockFreeQueue {
+            head,
+            tail,
+            pool,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Whether the queue is empty. Only a snapshot while other threads are
+    /// pushing or popping.
+    pub fn is_empty(&self) -> bool {
+        let mut guard = R::pin();
+        let head_ptr = R::protect(&mut guard, 0, &self.head);
+        unsafe { &*head_ptr }.next.load(Ordering::Acquire).is_null()
+    }
+
+    /// Number of nodes waiting in the pool to be reused.
+    pub fn pooled(&self) -> usize {
+        self.pool.as_ref().map_or(0, |pool| pool.len())
     }
 
-    pub fn push(&mut self, data: T) {
-        let new_node = Box::new(Node {
-            data: Some(data),
-            next: AtomicPtr::new(std::ptr::null_mut()),
-        });
+    /// Frees the nodes in the pool. Nodes that are still waiting for the
+    /// reclaimer will go back to the pool later.
+    pub fn shrink(&mut self) {
+        if let Some(pool) = &self.pool {
+            // The pool is only popped by `push`, and we have `&mut self`.
+            unsafe { pool.clear() };
+        }
+    }
+
+    fn alloc_node(&self, guard: &mut R::Guard, data: T) -> *mut Node<T> {
+        match self.pool.as_ref().and_then(|pool| pool.pop::<R>(guard)) {
+            Some(node) => {
+                unsafe { ptr::addr_of_mut!((*node).data).write(MaybeUninit::new(data)) };
+                unsafe { &*node }
+                    .next
+                    .store(ptr::null_mut(), Ordering::Relaxed);
+                node
+            }
+            None => Node::new(MaybeUninit::new(data)),
+        }
+    }
 
-        let new_node_ptr = Box::into_raw(new_node);
+    pub fn push(&self, data: T) {
+        let mut guard = R::pin();
+        let new_node_ptr = self.alloc_node(&mut guard, data);
 
         loop {
-            let tail_ptr = self.tail.load(Ordering::Acquire);
+            let tail_ptr = R::protect(&mut guard, 0, &self.tail);
             let tail_node = unsafe { &*tail_ptr };
             let next_ptr = tail_node.next.load(Ordering::Acquire);
 
@@ -65,17 +388,24 @@ impl<T> LockFreeQueue<T> {
                     Ordering::Relaxed,
                 );
 
-                break;
+                return;
             }
         }
     }
 
-    pub fn pop(&mut self) -> Option<T> {
+    /// Lock-free as long as nobody peeks: a pop that takes the value a
+    /// `peek` is cloning waits for the clone to finish.
+    pub fn pop(&self) -> Option<T> {
+        let mut guard = R::pin();
         loop {
-            let head_ptr = self.head.load(Ordering::Acquire);
+            let head_ptr = R::protect(&mut guard, 0, &self.head);
             let tail_ptr = self.tail.load(Ordering::Acquire);
             let head_node = unsafe { &*head_ptr };
-            let next_ptr = head_node.next.load(Ordering::Acquire);
+            let next_ptr = R::protect(&mut guard, 1, &head_node.next);
+            // `next_ptr` was protected too late if it has been popped since.
+            if self.head.load(Ordering::Acquire) != head_ptr {
+                continue;
+            }
 
             if head_ptr == tail_ptr {
                 if next_ptr.is_null() {
@@ -95,46 +425,328 @@ impl<T> LockFreeQueue<T> {
                 continue;
             }
 
-            let next_node = unsafe { &*next_ptr };
-            let data = next_node.data.as_ref().unwrap();
-
+            // SeqCst to order it with `peek`, see there.
             let result =
                 self.head
-                    .compare_exchange(head_ptr, next_ptr, Ordering::AcqRel, Ordering::Relaxed);
+                    .compare_exchange(head_ptr, next_ptr, Ordering::SeqCst, Ordering::Relaxed);
 
             if result.is_ok() {
-                let old_head_box = unsafe { Box::from_raw(head_ptr) };
+                // Peeks that started before the CAS may still be reading.
+                let next_node = unsafe { &*next_ptr };
+                while next_node.readers.load(Ordering::SeqCst) != 0 {
+                    hint::spin_loop();
+                }
 
-                // We can't move `data` out of `next_node` because it's behind a shared
-                // reference. The safe way is to take the Option<T> out, leaving None.
-                // However, since we are designing the queue, a simpler (but unsafe) way
-                // is to read the data using `ptr::read`. This is safe *only* because we know
-                // this node will become the new sentinel, and its data will never be read again.
-                let data = unsafe { ptr::read(data) };
+                // The next node is the new sentinel, so nobody else reads its data.
+                // `MaybeUninit` makes sure it isn't dropped a second time along
+                // with the node.
+                let data = unsafe { ptr::read(&next_node.data).assume_init() };
 
-                // Let the Box go out of scope, freeing the memory of the old sentinel.
-                drop(old_head_box);
+                // Other threads may still be looking at the old sentinel.
+                match &self.pool {
+                    Some(pool) => unsafe {
+                        let pool = Arc::into_raw(pool.clone()).cast_mut();
+                        (*head_ptr).pool.store(pool, Ordering::Relaxed);
+                        R::retire_with(&guard, head_ptr.cast(), recycle::<T, R>);
+                    },
+                    None => unsafe { R::retire(&guard, head_ptr) },
+                }
 
                 return Some(data);
             }
         }
     }
+
+    /// Returns a clone of the value at the front, without popping it.
+    ///
+    /// A concurrent `pop` of that value waits until the clone is done, so
+    /// a slow `clone` holds up the poppers.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone + Sync,
+    {
+        struct Reading<'a>(&'a AtomicUsize);
+
+        impl Drop for Reading<'_> {
+            fn drop(&mut self) {
+                // Release so our reads happen before the pop moves the data.
+                self.0.fetch_sub(1, Ordering::Release);
+            }
+        }
+
+        let mut guard = R::pin();
+        loop {
+            let head_ptr = R::protect(&mut guard, 0, &self.head);
+            let next_ptr = R::protect(&mut guard, 1, &unsafe { &*head_ptr }.next);
+            if self.head.load(Ordering::Acquire) != head_ptr {
+                continue;
+            }
+            if next_ptr.is_null() {
+                return None;
+            }
+
+            let next_node = unsafe { &*next_ptr };
+            next_node.readers.fetch_add(1, Ordering::SeqCst);
+            let _reading = Reading(&next_node.readers);
+            // Either the pop's CAS comes first in the SeqCst order and we see
+            // the head move on, or the pop sees our count and waits for it.
+            if self.head.load(Ordering::SeqCst) != head_ptr {
+                continue;
+            }
+            return Some(unsafe { next_node.data.assume_init_ref() }.clone());
+        }
+    }
+}
+
+impl<T, R: Reclaim> Default for LockFreeQueue<T, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A `LockFreeQueue` that holds at most `capacity` values. `try_push` hands
+/// the value back once it's full.
+pub struct BoundedQueue<T, R: Reclaim = Epoch> {
+    queue: LockFreeQueue<T, R>,
+    /// Counts pushes that have reserved their place, and pops that haven't
+    /// given theirs back yet, so it's only exact while nobody is pushing
+    /// or popping.
+    len: AtomicUsize,
+    capacity: usize,
+}
+
+impl<T, R: Reclaim> BoundedQueue<T, R> {
+    pub fn new(capacity: usize) -> Self {
+        BoundedQueue {
+            queue: LockFreeQueue::new(),
+            len: AtomicUsize::new(0),
+            capacity,
+        }
+    }
+
+    /// Number of values in the queue. Only approximate while other threads
+    /// are pushing or popping.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Pushes `data`, or hands it back if the queue is full.
+    pub fn try_push(&self, data: T) -> Result<(), T> {
+        if self.len.fetch_add(1, Ordering::Relaxed) >= self.capacity {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            return Err(data);
+        }
+        self.queue.push(data);
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let data = self.queue.pop()?;
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        Some(data)
+    }
+
+    /// See `LockFreeQueue::peek`.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone + Sync,
+    {
+        self.queue.peek()
+    }
 }
 
-impl<T> Drop for LockFreeQueue<T> {
+impl<T, R: Reclaim> Drop for LockFreeQueue<T, R> {
     fn drop(&mut self) {
-        // Keep popping until the queue is empty.
-        while let Some(_) = self.pop() {
-            // The `pop` method handles deallocation of the old head node.
+        // We have exclusive access, so no other thread can be reading any
+        // node and they can be freed right away, without going through `R`.
+        let mut node = unsafe { Box::from_raw(*self.head.get_mut()) };
+        loop {
+            let next_ptr = *node.next.get_mut();
+            drop(node);
+            if next_ptr.is_null() {
+                break;
+            }
+            node = unsafe { Box::from_raw(next_ptr) };
+            // Every node after the sentinel still holds its data.
+            unsafe { node.data.assume_init_drop() };
         }
+    }
+}
 
-        // Deallocate the final sentinel node.
-        // `get_mut` gives us a mutable reference, which is safe because
-        // we are in `drop` and have exclusive access.
-        let sentinel_ptr = *self.head.get_mut();
-        if !sentinel_ptr.is_null() {
-            // Convert the last raw pointer back to a Box to be dropped.
-            let _ = unsafe { Box::from_raw(sentinel_ptr) };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        fn push_pop<R: Reclaim>() {
+            let q = LockFreeQueue::<String, R>::new();
+            assert_eq!(q.pop(), None);
+            for i in 0..200 {
+                q.push(i.to_string());
+            }
+            for i in 0..100 {
+                assert_eq!(q.pop(), Some(i.to_string()));
+            }
+            // The rest is dropped with the queue.
         }
+
+        push_pop::<Epoch>();
+        push_pop::<Hazard>();
+    }
+
+    #[test]
+    fn test_concurrent() {
+        const PRODUCERS: usize = 4;
+        const CONSUMERS: usize = 4;
+        const PER_PRODUCER: usize = 10_000;
+
+        fn run<R: Reclaim>(q: LockFreeQueue<(usize, usize), R>) {
+            let popped = AtomicUsize::new(0);
+
+            let received = thread::scope(|s| {
+                for p in 0..PRODUCERS {
+                    let q = &q;
+                    s.spawn(move || {
+                        for i in 0..PER_PRODUCER {
+                            q.push((p, i));
+                        }
+                    });
+                }
+                let consumers: Vec<_> = (0..CONSUMERS)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let mut received = Vec::new();
+                            while popped.load(Ordering::Relaxed) < PRODUCERS * PER_PRODUCER {
+                                if let Some(item) = q.pop() {
+                                    popped.fetch_add(1, Ordering::Relaxed);
+                                    received.push(item);
+                                }
+                            }
+                            received
+                        })
+                    })
+                    .collect();
+                consumers
+                    .into_iter()
+                    .map(|c| c.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+
+            // Each consumer sees every producer's items in order.
+            for items in &received {
+                let mut last = [None; PRODUCERS];
+                for &(p, i) in items {
+                    assert!(last[p] < Some(i));
+                    last[p] = Some(i);
+                }
+            }
+            let mut all: Vec<_> = received.into_iter().flatten().collect();
+            all.sort_unstable();
+            let expected: Vec<_> = (0..PRODUCERS)
+                .flat_map(|p| (0..PER_PRODUCER).map(move |i| (p, i)))
+                .collect();
+            assert_eq!(all, expected);
+            assert_eq!(q.pop(), None);
+        }
+
+        run::<Epoch>(LockFreeQueue::new());
+        run::<Hazard>(LockFreeQueue::new());
+        run::<Epoch>(LockFreeQueue::with_pool(64));
+        run::<Hazard>(LockFreeQueue::with_pool(64));
+    }
+
+    #[test]
+    fn test_pool() {
+        let mut q = LockFreeQueue::<String, Hazard>::with_pool(8);
+        for i in 0..100 {
+            q.push(i.to_string());
+        }
+        for i in 0..100 {
+            assert_eq!(q.pop(), Some(i.to_string()));
+        }
+        // Nothing protects the popped nodes anymore.
+        crate::hazard::flush();
+        assert_eq!(q.pooled(), 8);
+
+        // Pushes take their nodes from the pool first.
+        for i in 0..5 {
+            q.push(i.to_string());
+        }
+        assert_eq!(q.pooled(), 3);
+        q.shrink();
+        assert_eq!(q.pooled(), 0);
+        for i in 0..5 {
+            assert_eq!(q.pop(), Some(i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_bounded() {
+        let q = BoundedQueue::<String>::new(2);
+        assert_eq!(q.capacity(), 2);
+        assert!(q.is_empty());
+        assert_eq!(q.peek(), None);
+
+        assert_eq!(q.try_push("a".to_string()), Ok(()));
+        assert_eq!(q.try_push("b".to_string()), Ok(()));
+        assert_eq!(q.try_push("c".to_string()), Err("c".to_string()));
+        assert_eq!(q.len(), 2);
+
+        assert_eq!(q.peek(), Some("a".to_string()));
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.pop(), Some("a".to_string()));
+        assert_eq!(q.try_push("c".to_string()), Ok(()));
+        assert_eq!(q.peek(), Some("b".to_string()));
+        assert_eq!(q.len(), 2);
+    }
+
+    #[test]
+    fn test_peek_concurrent() {
+        const N: usize = 10_000;
+
+        fn run<R: Reclaim>() {
+            let q = LockFreeQueue::<String, R>::new();
+            let done = AtomicUsize::new(0);
+
+            thread::scope(|s| {
+                s.spawn(|| {
+                    for i in 0..N {
+                        q.push(i.to_string());
+                    }
+                });
+                for _ in 0..2 {
+                    s.spawn(|| {
+                        while done.load(Ordering::Relaxed) < N {
+                            if q.pop().is_some() {
+                                done.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    });
+                }
+                // Peeked strings must not have been moved out from under us.
+                s.spawn(|| {
+                    while done.load(Ordering::Relaxed) < N {
+                        if let Some(value) = q.peek() {
+                            assert!(value.parse::<usize>().unwrap() < N);
+                        }
+                    }
+                });
+            });
+            assert!(q.is_empty());
+        }
+
+        run::<Epoch>();
+        run::<Hazard>();
     }
 }
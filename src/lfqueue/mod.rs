@@ -1,2 +1,10 @@
-mod lockfreequeue;
-mod fifo;
\ No newline at end of file
+pub mod lockfreequeue;
+pub mod fifo;
+pub mod mpmc;
+pub mod segqueue;
+pub mod array_fifo;
+pub mod growable_fifo;
+pub mod overwrite_fifo;
+pub mod broadcast;
+pub mod spmc;
+pub mod fanin;
\ No newline at end of file
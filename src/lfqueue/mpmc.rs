@@ -0,0 +1,255 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use super::fifo::CachePadded;
+
+/// Dmitry Vyukov's bounded MPMC queue.
+///
+///  - Every slot carries a sequence number, which tells a producer or
+///    consumer at position `pos` whether the slot is ready for it:
+///    `2 * pos` when it's free for a push, `2 * pos + 1` once it has been
+///    written. Doubling keeps "written at `pos`" apart from "free at
+///    `pos + capacity`", which are the same number with a capacity of 1.
+///  - Producers and consumers claim a position with a CAS on `head` or
+///    `tail`, then hand the slot over by storing its next sequence number.
+///    They never touch each other's index, only the slots.
+struct Shared<T: Send> {
+    buffer: Vec<Slot<T>>,
+    capacity: usize,
+    head: CachePadded<AtomicU64>,
+    tail: CachePadded<AtomicU64>,
+}
+
+struct Slot<T> {
+    seq: AtomicU64,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+pub struct Producer<T: Send> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Consumer<T: Send> {
+    shared: Arc<Shared<T>>,
+}
+
+// By hand, as a derive would require `T: Clone`.
+impl<T: Send> Clone for Producer<T> {
+    fn clone(&self) -> Self {
+        Producer {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T: Send> Clone for Consumer<T> {
+    fn clone(&self) -> Self {
+        Consumer {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+pub fn new<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0);
+
+    let buffer = (0..capacity as u64)
+        .map(|i| Slot {
+            seq: AtomicU64::new(2 * i),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect();
+    let shared = Arc::new(Shared {
+        buffer,
+        capacity,
+        head: CachePadded(AtomicU64::new(0)),
+        tail: CachePadded(AtomicU64::new(0)),
+    });
+
+    (
+        Producer {
+            shared: shared.clone(),
+        },
+        Consumer { shared },
+    )
+}
+
+impl<T: Send> Shared<T> {
+    fn slot(&self, pos: u64) -> &Slot<T> {
+        unsafe {
+            self.buffer
+                .get_unchecked((pos % self.capacity as u64) as usize)
+        }
+    }
+}
+
+impl<T: Send> Producer<T> {
+    /// Pushes `value`, or hands it back if the queue is full.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        let shared = &*self.shared;
+        let mut pos = shared.head.0.load(Ordering::Relaxed);
+        loop {
+            let slot = shared.slot(pos);
+            // Acquire to see the consumer's read of the old value finished.
+            let seq = slot.seq.load(Ordering::Acquire);
+            match seq.wrapping_sub(2 * pos) as i64 {
+                0 => match shared.head.0.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.seq.store(2 * pos + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                },
+                // The slot still holds the value from one lap ago.
+                diff if diff < 0 => return Err(value),
+                // Another producer has claimed `pos` already.
+                _ => pos = shared.head.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+}
+
+impl<T: Send> Consumer<T> {
+    pub fn try_pop(&self) -> Option<T> {
+        let shared = &*self.shared;
+        let mut pos = shared.tail.0.load(Ordering::Relaxed);
+        loop {
+            let slot = shared.slot(pos);
+            // Acquire to see the producer's write of the value.
+            let seq = slot.seq.load(Ordering::Acquire);
+            match seq.wrapping_sub(2 * pos + 1) as i64 {
+                0 => match shared.tail.0.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        // Free for the producer one lap later.
+                        slot.seq
+                            .store(2 * (pos + shared.capacity as u64), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                },
+                // Nothing has been written at `pos` yet.
+                diff if diff < 0 => return None,
+                // Another consumer has claimed `pos` already.
+                _ => pos = shared.tail.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+}
+
+impl<T: Send> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // No handles are left, so every position between tail and head
+        // holds a value.
+        let (tail, head) = (*self.tail.0.get_mut(), *self.head.0.get_mut());
+        for pos in tail..head {
+            let index = (pos % self.capacity as u64) as usize;
+            unsafe { self.buffer[index].value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        let (tx, rx) = new::<String>(2);
+        assert_eq!(rx.try_pop(), None);
+        assert_eq!(tx.try_push("a".to_string()), Ok(()));
+        assert_eq!(tx.try_push("b".to_string()), Ok(()));
+        assert_eq!(tx.try_push("c".to_string()), Err("c".to_string()));
+        assert_eq!(rx.try_pop(), Some("a".to_string()));
+        assert_eq!(tx.try_push("c".to_string()), Ok(()));
+        // "b" and "c" are dropped with the queue.
+
+        // Handles can be cloned whatever the payload.
+        #[derive(Debug, PartialEq)]
+        struct NoClone(u32);
+
+        let (tx, rx) = new::<NoClone>(1);
+        assert_eq!(tx.clone().try_push(NoClone(1)), Ok(()));
+        assert_eq!(rx.clone().try_pop(), Some(NoClone(1)));
+
+        // With a single slot, a full queue stays full until it's popped.
+        let (tx, rx) = new::<String>(1);
+        assert_eq!(tx.try_push("a".to_string()), Ok(()));
+        assert_eq!(tx.try_push("b".to_string()), Err("b".to_string()));
+        assert_eq!(rx.try_pop(), Some("a".to_string()));
+        assert_eq!(rx.try_pop(), None);
+        assert_eq!(tx.try_push("b".to_string()), Ok(()));
+        assert_eq!(tx.try_push("c".to_string()), Err("c".to_string()));
+        // "b" is dropped with the queue.
+    }
+
+    #[test]
+    fn test_concurrent() {
+        const PER_PRODUCER: usize = 10_000;
+
+        let (tx, rx) = new::<usize>(16);
+        let sum = AtomicUsize::new(0);
+        let popped = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let tx = tx.clone();
+                s.spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        let mut value = i;
+                        while let Err(v) = tx.try_push(value) {
+                            value = v;
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            for _ in 0..4 {
+                let rx = rx.clone();
+                let (sum, popped) = (&sum, &popped);
+                s.spawn(move || {
+                    while popped.load(Ordering::Relaxed) < 4 * PER_PRODUCER {
+                        match rx.try_pop() {
+                            Some(value) => {
+                                sum.fetch_add(value, Ordering::Relaxed);
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            sum.load(Ordering::Relaxed),
+            4 * PER_PRODUCER * (PER_PRODUCER - 1) / 2
+        );
+        assert_eq!(rx.try_pop(), None);
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering, fence},
+    },
+};
+
+use super::fifo::CachePadded;
+
+/// An SPSC ring that never makes the producer wait: when it's full, a push
+/// overwrites the oldest value instead, and the consumer finds out how many
+/// it missed.
+///
+///  - The producer never looks at the consumer, so there's no shared `tail`.
+///  - Every slot is a seqlock. Its sequence number is odd while the producer
+///    is writing it, and `2 * pos + 2` once the value for `pos` is in.
+///  - The consumer copies a value out, then checks that the sequence number
+///    hasn't changed. If it has, the copy may be torn and is thrown away,
+///    which is why `T` has to be `Copy`.
+struct Shared<T: Copy> {
+    buffer: Vec<Slot<T>>,
+    capacity: usize,
+    head: CachePadded<AtomicU64>,
+}
+
+struct Slot<T> {
+    seq: AtomicU64,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Copy + Send> Sync for Shared<T> {}
+
+pub struct Producer<T: Copy + Send> {
+    shared: Arc<Shared<T>>,
+    head: u64,
+}
+
+pub struct Consumer<T: Copy + Send> {
+    shared: Arc<Shared<T>>,
+    tail: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PopError {
+    Empty,
+    /// The producer overwrote this many values before we got to them. The
+    /// next `pop` returns the oldest one still there.
+    Missed(u64),
+}
+
+pub fn new<T: Copy + Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0);
+
+    let buffer = (0..capacity)
+        .map(|_| Slot {
+            seq: AtomicU64::new(0),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect();
+    let shared = Arc::new(Shared {
+        buffer,
+        capacity,
+        head: CachePadded(AtomicU64::new(0)),
+    });
+
+    (
+        Producer {
+            shared: shared.clone(),
+            head: 0,
+        },
+        Consumer { shared, tail: 0 },
+    )
+}
+
+impl<T: Copy + Send> Shared<T> {
+    fn slot(&self, pos: u64) -> &Slot<T> {
+        unsafe {
+            self.buffer
+                .get_unchecked((pos % self.capacity as u64) as usize)
+        }
+    }
+}
+
+impl<T: Copy + Send> Producer<T> {
+    /// Pushes `value`, overwriting the oldest value if the ring is full.
+    pub fn push(&mut self, value: T) {
+        let slot = self.shared.slot(self.head);
+        slot.seq.store(2 * self.head + 1, Ordering::Relaxed);
+        // Keeps the write below from being seen before the odd number.
+        fence(Ordering::Release);
+        // Volatile, as the consumer may be copying the old value right now.
+        unsafe { ptr::write_volatile(slot.value.get(), MaybeUninit::new(value)) };
+        slot.seq.store(2 * self.head + 2, Ordering::Release);
+
+        self.head += 1;
+        self.shared.head.0.store(self.head, Ordering::Release);
+    }
+}
+
+impl<T: Copy + Send> Consumer<T> {
+    pub fn pop(&mut self) -> Result<T, PopError> {
+        let capacity = self.shared.capacity as u64;
+        loop {
+            let head = self.shared.head.0.load(Ordering::Acquire);
+            if self.tail == head {
+                return Err(PopError::Empty);
+            }
+            if head - self.tail > capacity {
+                let missed = head - capacity - self.tail;
+                self.tail += missed;
+                return Err(PopError::Missed(missed));
+            }
+
+            let slot = self.shared.slot(self.tail);
+            // Acquire to see the value written before the even number.
+            let seq = slot.seq.load(Ordering::Acquire);
+            if seq != 2 * self.tail + 2 {
+                // Overwritten since we loaded `head`.
+                continue;
+            }
+            let value = unsafe { ptr::read_volatile(slot.value.get()) };
+            // Keeps the copy above from being done after the check below.
+            fence(Ordering::Acquire);
+            if slot.seq.load(Ordering::Relaxed) != seq {
+                continue;
+            }
+
+            self.tail += 1;
+            return Ok(unsafe { value.assume_init() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        let (mut producer, mut consumer) = new::<u32>(4);
+        assert_eq!(consumer.pop(), Err(PopError::Empty));
+
+        producer.push(0);
+        assert_eq!(consumer.pop(), Ok(0));
+        for i in 1..10 {
+            producer.push(i);
+        }
+        assert_eq!(consumer.pop(), Err(PopError::Missed(5)));
+        for i in 6..10 {
+            assert_eq!(consumer.pop(), Ok(i));
+        }
+        assert_eq!(consumer.pop(), Err(PopError::Empty));
+    }
+
+    #[test]
+    fn test_concurrent() {
+        const N: u64 = 100_000;
+
+        let (mut producer, mut consumer) = new::<[u64; 8]>(16);
+        let t = thread::spawn(move || {
+            for i in 0..N {
+                producer.push([i; 8]);
+            }
+        });
+
+        let mut received = 0;
+        let mut missed = 0;
+        let mut last = None;
+        while received + missed < N {
+            match consumer.pop() {
+                Ok(value) => {
+                    // Never a torn copy.
+                    assert!(value.iter().all(|&v| v == value[0]));
+                    assert!(last < Some(value[0]));
+                    last = Some(value[0]);
+                    received += 1;
+                }
+                Err(PopError::Missed(n)) => missed += n,
+                Err(PopError::Empty) => thread::yield_now(),
+            }
+        }
+        t.join().unwrap();
+        assert_eq!(last, Some(N - 1));
+        assert_eq!(consumer.pop(), Err(PopError::Empty));
+    }
+}
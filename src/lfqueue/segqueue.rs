@@ -0,0 +1,385 @@
+use std::{
+    array,
+    cell::UnsafeCell,
+    hint,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering},
+    thread,
+};
+
+use super::lockfreequeue::{Epoch, Reclaim};
+use crate::arc::Arc;
+
+const SEGMENT_SIZE: usize = 32;
+
+// Slot states.
+const EMPTY: u8 = 0;
+const WRITTEN: u8 = 1;
+const TAKEN: u8 = 2;
+
+struct Slot<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct Segment<T> {
+    /// Next slot index to hand to a `push`. Goes past `SEGMENT_SIZE` once
+    /// the segment is full.
+    enq_idx: AtomicUsize,
+    /// Next slot index to hand to a `pop`. Never ahead of `enq_idx`, and
+    /// stops at `SEGMENT_SIZE`.
+    deq_idx: AtomicUsize,
+    slots: [Slot<T>; SEGMENT_SIZE],
+    next: AtomicPtr<Segment<T>>,
+    /// The spare slot a retired segment goes back to, holding a reference
+    /// to it. Only set between `retire_with` and `recycle`.
+    spare: AtomicPtr<Spare<T>>,
+}
+
+impl<T> Segment<T> {
+    fn new() -> *mut Self {
+        Box::into_raw(Box::new(Segment {
+            enq_idx: AtomicUsize::new(0),
+            deq_idx: AtomicUsize::new(0),
+            slots: array::from_fn(|_| Slot {
+                state: AtomicU8::new(EMPTY),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }),
+            next: AtomicPtr::new(ptr::null_mut()),
+            spare: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+
+    /// Makes an unshared segment as good as new.
+    fn reset(&mut self) {
+        *self.enq_idx.get_mut() = 0;
+        *self.deq_idx.get_mut() = 0;
+        for slot in &mut self.slots {
+            *slot.state.get_mut() = EMPTY;
+        }
+        *self.next.get_mut() = ptr::null_mut();
+    }
+}
+
+/// One empty segment, kept to be reused by the next `push` that needs one.
+struct Spare<T>(AtomicPtr<Segment<T>>);
+
+impl<T> Drop for Spare<T> {
+    fn drop(&mut self) {
+        let segment = *self.0.get_mut();
+        if !segment.is_null() {
+            unsafe { drop(Box::from_raw(segment)) };
+        }
+    }
+}
+
+/// Hands a retired segment back to the queue's spare slot.
+unsafe fn recycle<T>(segment: *mut u8) {
+    let segment = segment.cast::<Segment<T>>();
+    let spare = unsafe { Arc::<Spare<T>>::from_raw(*(*segment).spare.get_mut()) };
+    unsafe { (*segment).reset() };
+    if spare
+        .0
+        .compare_exchange(
+            ptr::null_mut(),
+            segment,
+            Ordering::Release,
+            Ordering::Relaxed,
+        )
+        .is_err()
+    {
+        unsafe { drop(Box::from_raw(segment)) };
+    }
+}
+
+/// An unbounded MPMC queue made of fixed-size segments, so a push only
+/// allocates once every `SEGMENT_SIZE` values, if at all.
+///
+///  - Pushes claim a slot with a `fetch_add` on the segment's index, rather
+///    than racing with a CAS. Pops CAS theirs, so they only ever claim a
+///    slot some push has claimed already.
+///  - A pop that gets to a slot before its push has written it waits for
+///    the push, which is bound to come, instead of invalidating the slot and
+///    sending the push elsewhere, which could starve pushers.
+///  - Drained segments are retired through `R`, and one of them is kept
+///    around to be reused for the next segment.
+pub struct SegQueue<T, R: Reclaim = Epoch> {
+    head: AtomicPtr<Segment<T>>,
+    tail: AtomicPtr<Segment<T>>,
+    len: AtomicUsize,
+    // In an `Arc`, as retired segments may come back after the queue is gone.
+    spare: Arc<Spare<T>>,
+    _marker: PhantomData<(T, R)>,
+}
+
+unsafe impl<T: Send, R: Reclaim> Send for SegQueue<T, R> {}
+unsafe impl<T: Send, R: Reclaim> Sync for SegQueue<T, R> {}
+
+impl<T, R: Reclaim> SegQueue<T, R> {
+    pub fn new() -> Self {
+        let segment = Segment::new();
+        SegQueue {
+            head: AtomicPtr::new(segment),
+            tail: AtomicPtr::new(segment),
+            len: AtomicUsize::new(0),
+            spare: Arc::new(Spare(AtomicPtr::new(ptr::null_mut()))),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Number of values in the queue. Only approximate while other threads
+    /// are pushing or popping.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&self, value: T) {
+        let mut value = value;
+        let mut guard = R::pin();
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        loop {
+            let tail_ptr = R::protect(&mut guard, 0, &self.tail);
+            let tail = unsafe { &*tail_ptr };
+
+            let idx = tail.enq_idx.fetch_add(1, Ordering::Relaxed);
+            if idx < SEGMENT_SIZE {
+                let slot = &tail.slots[idx];
+                unsafe { (*slot.value.get()).write(value) };
+                // Release to publish the value to the pop that takes it.
+                slot.state.store(WRITTEN, Ordering::Release);
+                return;
+            }
+
+            // The segment is full.
+            if self.tail.load(Ordering::Acquire) != tail_ptr {
+                continue;
+            }
+            let next_ptr = tail.next.load(Ordering::Acquire);
+            if !next_ptr.is_null() {
+                let _ = self.tail.compare_exchange(
+                    tail_ptr,
+                    next_ptr,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+                continue;
+            }
+
+            // Link a new segment that already holds the value.
+            let new_ptr = self.alloc_segment();
+            let new = unsafe { &mut *new_ptr };
+            new.slots[0].value.get_mut().write(value);
+            *new.slots[0].state.get_mut() = WRITTEN;
+            *new.enq_idx.get_mut() = 1;
+            match tail.next.compare_exchange(
+                ptr::null_mut(),
+                new_ptr,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    let _ = self.tail.compare_exchange(
+                        tail_ptr,
+                        new_ptr,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+                    return;
+                }
+                Err(_) => {
+                    value = unsafe { new.slots[0].value.get_mut().assume_init_read() };
+                    // Nobody else has seen it.
+                    unsafe { drop(Box::from_raw(new_ptr)) };
+                }
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let mut guard = R::pin();
+        loop {
+            let head_ptr = R::protect(&mut guard, 0, &self.head);
+            let head = unsafe { &*head_ptr };
+
+            if head.deq_idx.load(Ordering::Relaxed) >= head.enq_idx.load(Ordering::Relaxed)
+                && head.next.load(Ordering::Acquire).is_null()
+            {
+                return None;
+            }
+
+            let idx = head.deq_idx.load(Ordering::Relaxed);
+            if idx < SEGMENT_SIZE {
+                if idx >= head.enq_idx.load(Ordering::Relaxed) {
+                    return None;
+                }
+                if head
+                    .deq_idx
+                    .compare_exchange_weak(idx, idx + 1, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_err()
+                {
+                    continue;
+                }
+
+                let slot = &head.slots[idx];
+                // Acquire to see the value written by the push.
+                let mut spins = 0;
+                while slot.state.load(Ordering::Acquire) != WRITTEN {
+                    if spins < 10 {
+                        hint::spin_loop();
+                        spins += 1;
+                    } else {
+                        thread::yield_now();
+                    }
+                }
+                let value = unsafe { (*slot.value.get()).assume_init_read() };
+                slot.state.store(TAKEN, Ordering::Relaxed);
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                return Some(value);
+            }
+
+            // The segment is drained.
+            let next_ptr = head.next.load(Ordering::Acquire);
+            if next_ptr.is_null() {
+                return None;
+            }
+            // The tail may lag behind, and must never point at a retired segment.
+            let _ = self.tail.compare_exchange(
+                head_ptr,
+                next_ptr,
+                Ordering::Release,
+                Ordering::Relaxed,
+            );
+            if self
+                .head
+                .compare_exchange(head_ptr, next_ptr, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                unsafe {
+                    let spare = Arc::into_raw(self.spare.clone()).cast_mut();
+                    (*head_ptr).spare.store(spare, Ordering::Relaxed);
+                    R::retire_with(&guard, head_ptr.cast(), recycle::<T>);
+                }
+            }
+        }
+    }
+
+    fn alloc_segment(&self) -> *mut Segment<T> {
+        let spare = self.spare.0.swap(ptr::null_mut(), Ordering::Acquire);
+        if spare.is_null() {
+            Segment::new()
+        } else {
+            spare
+        }
+    }
+}
+
+impl<T, R: Reclaim> Default for SegQueue<T, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, R: Reclaim> Drop for SegQueue<T, R> {
+    fn drop(&mut self) {
+        // Every value that was pushed but not popped is in a `WRITTEN` slot.
+        let mut segment = *self.head.get_mut();
+        while !segment.is_null() {
+            let mut boxed = unsafe { Box::from_raw(segment) };
+            for slot in &mut boxed.slots {
+                if *slot.state.get_mut() == WRITTEN {
+                    unsafe { slot.value.get_mut().assume_init_drop() };
+                }
+            }
+            segment = *boxed.next.get_mut();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lfqueue::lockfreequeue::Hazard;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        let q = SegQueue::<String>::new();
+        assert_eq!(q.pop(), None);
+        for i in 0..3 * SEGMENT_SIZE {
+            q.push(i.to_string());
+        }
+        assert_eq!(q.len(), 3 * SEGMENT_SIZE);
+        for i in 0..2 * SEGMENT_SIZE + 1 {
+            assert_eq!(q.pop(), Some(i.to_string()));
+        }
+        // The rest, across two segments, is dropped with the queue.
+    }
+
+    #[test]
+    fn test_concurrent() {
+        const PER_PRODUCER: usize = 10_000;
+
+        fn run<R: Reclaim>() {
+            let q = SegQueue::<(usize, usize), R>::new();
+
+            let received = thread::scope(|s| {
+                for p in 0..4 {
+                    let q = &q;
+                    s.spawn(move || {
+                        for i in 0..PER_PRODUCER {
+                            q.push((p, i));
+                        }
+                    });
+                }
+                let consumers: Vec<_> = (0..4)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let mut received = Vec::new();
+                            for _ in 0..PER_PRODUCER {
+                                loop {
+                                    if let Some(item) = q.pop() {
+                                        received.push(item);
+                                        break;
+                                    }
+                                    thread::yield_now();
+                                }
+                            }
+                            received
+                        })
+                    })
+                    .collect();
+                consumers
+                    .into_iter()
+                    .map(|c| c.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+
+            // Each consumer sees every producer's items in order.
+            for items in &received {
+                let mut last = [None; 4];
+                for &(p, i) in items {
+                    assert!(last[p] < Some(i));
+                    last[p] = Some(i);
+                }
+            }
+            let mut all: Vec<_> = received.into_iter().flatten().collect();
+            all.sort_unstable();
+            let expected: Vec<_> = (0..4)
+                .flat_map(|p| (0..PER_PRODUCER).map(move |i| (p, i)))
+                .collect();
+            assert_eq!(all, expected);
+            assert!(q.is_empty());
+            assert_eq!(q.pop(), None);
+        }
+
+        run::<Epoch>();
+        run::<Hazard>();
+    }
+}
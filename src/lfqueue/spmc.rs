@@ -0,0 +1,303 @@
+use std::{
+    cell::UnsafeCell,
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::Deref,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use super::fifo::CachePadded;
+pub use super::fifo::FullError;
+
+/// A bounded SPMC queue that hands every value to exactly one of its
+/// consumers.
+///
+///  - The producer publishes `head` as in `fifo`.
+///  - Consumers claim a position with a CAS on `tail`, so each one is taken
+///    once, but a claimed slot may still be being read. That's why the
+///    producer doesn't gate on `tail`: every slot has a stamp that the
+///    consumer sets to `pos + capacity` once it's done, and the producer
+///    only reuses a slot whose stamp says it's free for `head`.
+struct Shared<T: Send> {
+    buffer: Vec<Slot<T>>,
+    capacity: usize,
+    head: CachePadded<AtomicU64>,
+    tail: CachePadded<AtomicU64>,
+}
+
+struct Slot<T> {
+    /// The next position this slot is free to be written for.
+    stamp: AtomicU64,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+pub struct Producer<T: Send> {
+    shared: Arc<Shared<T>>,
+    head: u64,
+}
+
+pub struct Consumer<T: Send> {
+    shared: Arc<Shared<T>>,
+    // Local cache of producer's head to reduce atomic loads
+    cache_head: u64,
+}
+
+impl<T: Send> Clone for Consumer<T> {
+    fn clone(&self) -> Self {
+        Consumer {
+            shared: self.shared.clone(),
+            cache_head: self.cache_head,
+        }
+    }
+}
+
+pub struct Pusher<'a, T: Send> {
+    producer: &'a mut Producer<T>,
+    slot: *mut MaybeUninit<T>,
+}
+
+impl<T: Send> Pusher<'_, T> {
+    /// Writes the slot and hands it to the consumers. Dropping the `Pusher`
+    /// without writing gives the slot back instead.
+    pub fn write(self, value: T) {
+        unsafe { (*self.slot).write(value) };
+        self.producer.head += 1;
+        self.producer
+            .shared
+            .head
+            .0
+            .store(self.producer.head, Ordering::Release);
+    }
+}
+
+/// A value claimed by one consumer. As with `fifo`'s, dropping it without
+/// taking drops the value.
+pub struct Popper<'a, T: Send> {
+    shared: &'a Shared<T>,
+    pos: u64,
+}
+
+impl<T: Send> Popper<'_, T> {
+    pub fn take(self) -> T {
+        let this = ManuallyDrop::new(self);
+        let value = unsafe { (*this.slot().value.get()).assume_init_read() };
+        this.release();
+        value
+    }
+
+    pub fn peek(&self) -> &T {
+        unsafe { (*self.slot().value.get()).assume_init_ref() }
+    }
+
+    fn slot(&self) -> &Slot<T> {
+        self.shared.slot(self.pos)
+    }
+
+    fn release(&self) {
+        // Release so our read is done before the producer reuses the slot.
+        let stamp = self.pos + self.shared.capacity as u64;
+        self.slot().stamp.store(stamp, Ordering::Release);
+    }
+}
+
+impl<T: Send> Deref for Popper<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.peek()
+    }
+}
+
+impl<T: Send> Drop for Popper<'_, T> {
+    fn drop(&mut self) {
+        unsafe { (*self.slot().value.get()).assume_init_drop() };
+        self.release();
+    }
+}
+
+pub fn new<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0);
+
+    let buffer = (0..capacity as u64)
+        .map(|i| Slot {
+            stamp: AtomicU64::new(i),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect();
+    let shared = Arc::new(Shared {
+        buffer,
+        capacity,
+        head: CachePadded(AtomicU64::new(0)),
+        tail: CachePadded(AtomicU64::new(0)),
+    });
+
+    (
+        Producer {
+            shared: shared.clone(),
+            head: 0,
+        },
+        Consumer {
+            shared,
+            cache_head: 0,
+        },
+    )
+}
+
+impl<T: Send> Shared<T> {
+    fn slot(&self, pos: u64) -> &Slot<T> {
+        unsafe {
+            self.buffer
+                .get_unchecked((pos % self.capacity as u64) as usize)
+        }
+    }
+}
+
+impl<T: Send> Producer<T> {
+    pub fn push(&mut self) -> Result<Pusher<'_, T>, FullError> {
+        let slot = self.shared.slot(self.head);
+        // Acquire to see the consumer's read of the old value finished.
+        if slot.stamp.load(Ordering::Acquire) != self.head {
+            return Err(FullError);
+        }
+        Ok(Pusher {
+            slot: slot.value.get(),
+            producer: self,
+        })
+    }
+
+    /// Pushes `value`, or hands it back if the queue is full.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        match self.push() {
+            Ok(pusher) => {
+                pusher.write(value);
+                Ok(())
+            }
+            Err(FullError) => Err(value),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+}
+
+impl<T: Send> Consumer<T> {
+    /// Claims the oldest value, so no other consumer gets it.
+    pub fn pop(&mut self) -> Option<Popper<'_, T>> {
+        let shared = &*self.shared;
+        let mut tail = shared.tail.0.load(Ordering::Relaxed);
+        loop {
+            // Other consumers may have moved `tail` past our cached head.
+            if tail >= self.cache_head {
+                // Update local cache of head
+                self.cache_head = shared.head.0.load(Ordering::Acquire);
+
+                if tail >= self.cache_head {
+                    return None;
+                }
+            }
+
+            match shared.tail.0.compare_exchange_weak(
+                tail,
+                tail + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(Popper { shared, pos: tail }),
+                Err(current) => tail = current,
+            }
+        }
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        self.pop().map(Popper::take)
+    }
+}
+
+impl<T: Send> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // No handles are left, so every position between tail and head
+        // holds a value.
+        let (tail, head) = (*self.tail.0.get_mut(), *self.head.0.get_mut());
+        for pos in tail..head {
+            let index = (pos % self.capacity as u64) as usize;
+            unsafe { self.buffer[index].value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    #[test]
+    fn test() {
+        let (mut producer, mut a) = new::<String>(2);
+        let mut b = a.clone();
+
+        // Dropping a `Pusher` aborts: nothing is published.
+        let _ = producer.push().unwrap();
+        assert!(a.pop().is_none());
+
+        producer.push().unwrap().write("x".to_string());
+        producer.try_push("y".to_string()).unwrap();
+        assert_eq!(producer.try_push("z".to_string()), Err("z".to_string()));
+
+        // A claimed value is only seen by the consumer that claimed it, and
+        // its slot stays taken until it's done with it.
+        let popper = a.pop().unwrap();
+        assert_eq!(&*popper, "x");
+        // "y" is dropped with its `Popper`.
+        assert_eq!(b.pop().unwrap().peek(), "y");
+        assert!(b.pop().is_none());
+        assert_eq!(producer.try_push("z".to_string()), Err("z".to_string()));
+        assert_eq!(popper.take(), "x");
+        producer.try_push("z".to_string()).unwrap();
+        assert_eq!(b.try_pop(), Some("z".to_string()));
+        producer.try_push("w".to_string()).unwrap();
+        // "w" is dropped with the queue.
+    }
+
+    #[test]
+    fn test_concurrent() {
+        const N: usize = 10_000;
+
+        let (mut producer, consumer) = new::<usize>(16);
+        let sum = AtomicUsize::new(0);
+        let popped = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let mut consumer = consumer.clone();
+                let (sum, popped) = (&sum, &popped);
+                s.spawn(move || {
+                    while popped.load(Ordering::Relaxed) < N {
+                        match consumer.try_pop() {
+                            Some(value) => {
+                                sum.fetch_add(value, Ordering::Relaxed);
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                });
+            }
+            for i in 0..N {
+                let mut value = i;
+                while let Err(v) = producer.try_push(value) {
+                    value = v;
+                    thread::yield_now();
+                }
+            }
+        });
+
+        assert_eq!(sum.load(Ordering::Relaxed), N * (N - 1) / 2);
+        let mut consumer = consumer;
+        assert_eq!(consumer.try_pop(), None);
+    }
+}
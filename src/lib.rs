@@ -1,13 +1,19 @@
 #![allow(dead_code)]
 
-mod arc;
+pub mod allocator;
+pub mod arc;
+pub mod atomic_arc;
+pub mod biased_arc;
 mod channel;
 pub mod condvar;
-mod lfqueue;
+pub mod epoch;
+pub mod hazard;
+pub mod lfqueue;
 pub mod mutex;
 mod one_shot_ch;
 pub mod rwlock;
 mod spin_lock;
+pub mod thin_arc;
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
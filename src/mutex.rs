@@ -22,7 +22,7 @@ pub struct Guard<'a, T> {
 }
 
 impl<T> Mutex<T> {
-    pub fn new(value: T) -> Self {
+    pub const fn new(value: T) -> Self {
         Self {
             state: AtomicU32::new(0),
             value: UnsafeCell::new(value),
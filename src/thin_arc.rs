@@ -0,0 +1,259 @@
+use std::{
+    alloc::Layout,
+    marker::PhantomData,
+    mem::{self, ManuallyDrop, offset_of},
+    ops::Deref,
+    ptr::{self, NonNull},
+};
+
+use crate::arc::Arc;
+
+/// A header followed by a slice, laid out in a single allocation.
+/// The length of the slice is stored inline, so a pointer to it can be thin.
+#[repr(C)]
+pub struct HeaderSlice<H, T: ?Sized> {
+    pub header: H,
+    len: usize,
+    pub slice: T,
+}
+
+/// A single-word `Arc<HeaderSlice<H, [T]>>`.
+///
+/// It points at the `HeaderSlice` inside an ordinary `ArcData`, so it shares
+/// its reference counting with `Arc` and can be converted to and from one.
+/// The fat pointer is rebuilt from the inline length whenever it's needed.
+pub struct ThinArc<H, T> {
+    ptr: NonNull<HeaderSlice<H, [T; 0]>>,
+    _marker: PhantomData<Arc<HeaderSlice<H, [T]>>>,
+}
+
+unsafe impl<H: Send + Sync, T: Send + Sync> Send for ThinArc<H, T> {}
+unsafe impl<H: Send + Sync, T: Send + Sync> Sync for ThinArc<H, T> {}
+
+impl<H, T> ThinArc<H, T> {
+    pub fn from_header_and_iter<I>(header: H, items: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let mut items = items.into_iter();
+        let len = items.len();
+
+        // Same as `Layout::for_value` on the finished `HeaderSlice`,
+        // given that it's `repr(C)`.
+        let offset = offset_of!(HeaderSlice<H, [T; 0]>, slice);
+        let size = Layout::array::<T>(len)
+            .ok()
+            .and_then(|array| offset.checked_add(array.size()))
+            .expect("capacity overflow");
+        let align = align_of::<HeaderSlice<H, [T; 0]>>();
+        let layout = Layout::from_size_align(size, align)
+            .expect("capacity overflow")
+            .pad_to_align();
+
+        let metadata =
+            ptr::slice_from_raw_parts(ptr::null::<T>(), len) as *const HeaderSlice<H, [T]>;
+        let arc = unsafe {
+            Arc::new_in_place(layout, metadata, |data| {
+                let header_ptr = ptr::addr_of_mut!((*data).header);
+                header_ptr.write(header);
+                ptr::addr_of_mut!((*data).len).write(len);
+                // Drops what's been written if the iterator panics or comes up
+                // short. `new_in_place` frees the allocation.
+                let mut written = Written {
+                    header: header_ptr,
+                    slice: ptr::addr_of_mut!((*data).slice).cast::<T>(),
+                    len: 0,
+                };
+                for item in items.by_ref().take(len) {
+                    written.slice.add(written.len).write(item);
+                    written.len += 1;
+                }
+                assert_eq!(
+                    written.len, len,
+                    "ExactSizeIterator yielded fewer items than reported"
+                );
+                assert!(
+                    items.next().is_none(),
+                    "ExactSizeIterator yielded more items than reported"
+                );
+                mem::forget(written);
+            })
+        };
+        Self::from_arc(arc)
+    }
+
+    pub fn from_arc(arc: Arc<HeaderSlice<H, [T]>>) -> Self {
+        let ptr = Arc::into_raw(arc) as *mut HeaderSlice<H, [T; 0]>;
+        ThinArc {
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn into_arc(thin: Self) -> Arc<HeaderSlice<H, [T]>> {
+        let thin = ManuallyDrop::new(thin);
+        unsafe { Arc::from_raw(thin.fat_ptr()) }
+    }
+
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.ptr == b.ptr
+    }
+
+    pub fn strong_count(thin: &Self) -> usize {
+        thin.with_arc(Arc::strong_count)
+    }
+
+    /// Temporarily views `self` as the `Arc` it was made from.
+    fn with_arc<R>(&self, f: impl FnOnce(&Arc<HeaderSlice<H, [T]>>) -> R) -> R {
+        let arc = ManuallyDrop::new(unsafe { Arc::from_raw(self.fat_ptr()) });
+        f(&arc)
+    }
+
+    fn fat_ptr(&self) -> *const HeaderSlice<H, [T]> {
+        let thin = self.ptr.as_ptr();
+        // Safety: `len` is initialized for as long as any ThinArc exists.
+        let len = unsafe { (*thin).len };
+        ptr::slice_from_raw_parts(thin.cast::<T>(), len) as *const HeaderSlice<H, [T]>
+    }
+}
+
+/// The header and the first `len` items of a `HeaderSlice` being built.
+struct Written<H, T> {
+    header: *mut H,
+    slice: *mut T,
+    len: usize,
+}
+
+impl<H, T> Drop for Written<H, T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.slice, self.len));
+            ptr::drop_in_place(self.header);
+        }
+    }
+}
+
+impl<H, T> HeaderSlice<H, [T]> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<H, T> Deref for ThinArc<H, T> {
+    type Target = HeaderSlice<H, [T]>;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.fat_ptr() }
+    }
+}
+
+impl<H, T> Clone for ThinArc<H, T> {
+    fn clone(&self) -> Self {
+        Self::from_arc(self.with_arc(Arc::clone))
+    }
+}
+
+impl<H, T> Drop for ThinArc<H, T> {
+    fn drop(&mut self) {
+        unsafe { drop(Arc::<HeaderSlice<H, [T]>>::from_raw(self.fat_ptr())) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct DectorDrop;
+
+        impl Drop for DectorDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        assert_eq!(size_of::<ThinArc<u8, u64>>(), size_of::<usize>());
+
+        let a = ThinArc::from_header_and_iter((1u8, DectorDrop), (0..4).map(|_| DectorDrop));
+        let b = a.clone();
+        assert_eq!(ThinArc::strong_count(&a), 2);
+
+        thread::spawn(move || {
+            assert_eq!(b.header.0, 1);
+            assert_eq!(b.slice.len(), 4);
+        })
+        .join()
+        .unwrap();
+
+        let arc = ThinArc::into_arc(a);
+        assert_eq!(Arc::strong_count(&arc), 1);
+        assert_eq!(arc.len(), 4);
+        let a = ThinArc::from_arc(arc);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(a);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 5);
+
+        let s = ThinArc::from_header_and_iter(3usize, "abc".bytes());
+        assert_eq!(s.header, s.len());
+        assert_eq!(&s.slice, b"abc");
+        assert!(ThinArc::from_header_and_iter((), std::iter::empty::<u32>()).is_empty());
+    }
+
+    #[test]
+    fn test_unwind() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct DectorDrop;
+
+        impl Drop for DectorDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        /// Reports 4 items, but only has 2.
+        struct Short(usize);
+
+        impl Iterator for Short {
+            type Item = DectorDrop;
+
+            fn next(&mut self) -> Option<DectorDrop> {
+                (self.0 < 2).then(|| {
+                    self.0 += 1;
+                    DectorDrop
+                })
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (4 - self.0, Some(4 - self.0))
+            }
+        }
+
+        impl ExactSizeIterator for Short {}
+
+        // The header and the items written so far are dropped, and the
+        // allocation is freed.
+        let items = (0..4).map(|i| {
+            assert!(i < 2, "iterator panicked");
+            DectorDrop
+        });
+        let result = std::panic::catch_unwind(|| ThinArc::from_header_and_iter(DectorDrop, items));
+        assert!(result.is_err());
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+
+        let result =
+            std::panic::catch_unwind(|| ThinArc::from_header_and_iter(DectorDrop, Short(0)));
+        assert!(result.is_err());
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 6);
+    }
+}